@@ -0,0 +1,4 @@
+// Embedded migrations (`sqlx::migrate!`) are only refreshed when the crate is rebuilt.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
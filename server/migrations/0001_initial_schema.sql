@@ -0,0 +1,30 @@
+-- Schema before the migrations were introduced. `IF NOT EXISTS` lets the databases created by hand adopt it.
+
+CREATE TABLE IF NOT EXISTS users (
+    username TEXT NOT NULL PRIMARY KEY,
+    encrypted_password TEXT NOT NULL,
+    nickname TEXT NOT NULL,
+    profile_picture_filename TEXT,
+    created_at DATETIME NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS conversations (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    sender_username TEXT NOT NULL REFERENCES users(username),
+    text TEXT NOT NULL,
+    sent_at DATETIME NOT NULL,
+    conversation_id INTEGER NOT NULL REFERENCES conversations(id)
+);
+
+CREATE TABLE IF NOT EXISTS group_members (
+    username TEXT NOT NULL REFERENCES users(username),
+    conversation_id INTEGER NOT NULL REFERENCES conversations(id),
+    joined_at DATETIME NOT NULL,
+    PRIMARY KEY (username, conversation_id)
+);
@@ -0,0 +1,4 @@
+-- Message id generated by the sender, to store a resent message only once.
+ALTER TABLE messages ADD COLUMN client_id TEXT;
+
+CREATE UNIQUE INDEX messages_sender_client_id ON messages(sender_username, client_id);
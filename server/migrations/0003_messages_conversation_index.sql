@@ -0,0 +1,2 @@
+-- Cursor pagination of the messages in a conversation.
+CREATE INDEX messages_conversation_id ON messages(conversation_id, id);
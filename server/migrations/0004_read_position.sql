@@ -0,0 +1,2 @@
+-- Last message read by the member, for the unread count.
+ALTER TABLE group_members ADD COLUMN last_read_message_id INTEGER;
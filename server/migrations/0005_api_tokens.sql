@@ -0,0 +1,16 @@
+-- Personal access tokens, authenticating non-browser clients with `Authorization: Bearer {token}`.
+CREATE TABLE api_tokens (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL REFERENCES users(username) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- SHA-256 hex digest of the token, which is only shown once on creation.
+    token_hash TEXT NOT NULL UNIQUE,
+    -- Space separated scopes, e.g. "read write".
+    scopes TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    -- NULL if the token never expires.
+    expires_at DATETIME,
+    last_used_at DATETIME
+);
+
+CREATE INDEX api_tokens_username ON api_tokens(username);
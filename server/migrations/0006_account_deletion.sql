@@ -0,0 +1,25 @@
+-- Incremented when the password changes, to invalidate the sessions logged in with the previous password.
+ALTER TABLE users ADD COLUMN session_epoch INTEGER NOT NULL DEFAULT 0;
+
+-- Messages outlive their sender: deleting a user anonymizes the messages (`sender_username` becomes NULL).
+-- SQLite cannot alter a foreign key, so the table is rebuilt.
+CREATE TABLE messages_new (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    sender_username TEXT REFERENCES users(username) ON DELETE SET NULL,
+    text TEXT NOT NULL,
+    sent_at DATETIME NOT NULL,
+    conversation_id INTEGER NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    -- Message id generated by the sender, to store a resent message only once.
+    client_id TEXT
+);
+
+INSERT INTO messages_new (id, sender_username, text, sent_at, conversation_id, client_id)
+SELECT id, sender_username, text, sent_at, conversation_id, client_id FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
+
+CREATE UNIQUE INDEX messages_sender_client_id ON messages(sender_username, client_id);
+
+-- Cursor pagination of the messages in a conversation.
+CREATE INDEX messages_conversation_id ON messages(conversation_id, id);
@@ -0,0 +1,3 @@
+-- `text` messages are written by their sender. `system` messages are written by the server on behalf of their sender,
+-- to record an event of the conversation (e.g. "Alice added Bob").
+ALTER TABLE messages ADD COLUMN kind TEXT NOT NULL DEFAULT 'text' CHECK (kind IN ('text', 'system'));
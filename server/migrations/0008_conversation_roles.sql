@@ -0,0 +1,15 @@
+-- Role of the member in the conversation. The creator is the `owner`, who may appoint `admin`s; see
+-- `MemberRole` for what each role is allowed to do.
+ALTER TABLE group_members ADD COLUMN role TEXT NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'admin', 'member'));
+
+-- The creator of the existing conversations is unknown: the earliest member becomes the owner.
+UPDATE group_members SET role = 'owner'
+WHERE username = (
+    SELECT earliest.username FROM group_members earliest
+    WHERE earliest.conversation_id = group_members.conversation_id
+    ORDER BY earliest.joined_at, earliest.username
+    LIMIT 1
+);
+
+-- A conversation has at most one owner.
+CREATE UNIQUE INDEX group_members_owner ON group_members(conversation_id) WHERE role = 'owner';
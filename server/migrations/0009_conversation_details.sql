@@ -0,0 +1,4 @@
+-- Details editable by the owner and the admins. `avatar_filename` is stored with the profile pictures.
+ALTER TABLE conversations ADD COLUMN topic TEXT;
+ALTER TABLE conversations ADD COLUMN description TEXT;
+ALTER TABLE conversations ADD COLUMN avatar_filename TEXT;
@@ -0,0 +1,8 @@
+-- `direct` conversations are the one-to-one conversations got by `POST /api/conversation/direct`, and `group`
+-- conversations are the others. Existing conversations stay groups, even with two members.
+ALTER TABLE conversations ADD COLUMN kind TEXT NOT NULL DEFAULT 'group' CHECK (kind IN ('group', 'direct'));
+
+-- "{username}:{username}" of the two participants in ascending order, NULL for the groups. Unique, so that a pair of
+-- users has at most one direct conversation.
+ALTER TABLE conversations ADD COLUMN dm_key TEXT;
+CREATE UNIQUE INDEX conversations_dm_key ON conversations(dm_key) WHERE dm_key IS NOT NULL;
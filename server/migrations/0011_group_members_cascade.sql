@@ -0,0 +1,22 @@
+-- The foreign keys of `group_members` (see `0001_initial_schema.sql`) don't cascade: deleting a user or a
+-- conversation with members failed. SQLite cannot alter a foreign key, so the table is rebuilt.
+-- Memberships of the users or conversations which don't exist anymore are dropped.
+CREATE TABLE group_members_new (
+    username TEXT NOT NULL REFERENCES users(username) ON DELETE CASCADE,
+    conversation_id INTEGER NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    joined_at DATETIME NOT NULL,
+    -- Last message read by the member, for the unread count.
+    last_read_message_id INTEGER,
+    role TEXT NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'admin', 'member')),
+    PRIMARY KEY (username, conversation_id)
+);
+
+INSERT INTO group_members_new (username, conversation_id, joined_at, last_read_message_id, role)
+SELECT username, conversation_id, joined_at, last_read_message_id, role FROM group_members
+WHERE username IN (SELECT username FROM users) AND conversation_id IN (SELECT id FROM conversations);
+
+DROP TABLE group_members;
+ALTER TABLE group_members_new RENAME TO group_members;
+
+-- A conversation has at most one owner.
+CREATE UNIQUE INDEX group_members_owner ON group_members(conversation_id) WHERE role = 'owner';
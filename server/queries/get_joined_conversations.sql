@@ -0,0 +1,47 @@
+-- Page of the conversations joined by the user ?1, most recently active first: ?2 conversations from the offset ?3.
+-- Shared by `GET /api/conversation/joined` and `scripts/bench_joined_conversations.py`. Column names carry the sqlx
+-- type overrides.
+WITH joined AS (
+    SELECT conversation_id, last_read_message_id
+    FROM group_members
+    WHERE username = ?1
+),
+message_stats AS ( -- Correlated subqueries only read the messages index range of each conversation.
+    SELECT j.conversation_id,
+           (SELECT MAX(id) FROM messages WHERE conversation_id = j.conversation_id) AS last_message_id,
+           (SELECT COUNT(*)
+            FROM messages
+            WHERE conversation_id = j.conversation_id AND id > COALESCE(j.last_read_message_id, 0) AND sender_username IS NOT ?1) AS unread_count
+    FROM joined j
+),
+members AS (
+    SELECT conversation_id,
+           json_group_array(json_object('username', username, 'nickname', nickname, 'profile_picture_filename', profile_picture_filename)) AS members
+    FROM
+        (SELECT gm.conversation_id, users.username, users.nickname, users.profile_picture_filename
+        FROM group_members gm
+        INNER JOIN joined j USING (conversation_id)
+        INNER JOIN users USING (username)
+        WHERE gm.username != ?1
+        ORDER BY gm.joined_at ASC) -- json_group_array keeps this order.
+    GROUP BY conversation_id
+)
+SELECT c.id AS "id!",
+       c.kind AS "kind: ConversationKind",
+       CASE WHEN c.kind = 'direct' THEN COALESCE(json_extract(members.members, '$[0].nickname'), c.name)
+            ELSE c.name
+       END AS "name!: String",
+       c.topic,
+       c.avatar_filename,
+       COALESCE(members.members, json_array()) AS "members!: Json<Vec<User>>",
+       CASE WHEN m.id IS NULL THEN NULL
+            ELSE json_object('id', m.id, 'sender_username', m.sender_username, 'text', m.text, 'sent_at', m.sent_at, 'kind', m.kind)
+       END AS "last_message: Json<Message>",
+       ms.unread_count AS "unread_count!: i64"
+FROM joined j
+INNER JOIN conversations c ON c.id = j.conversation_id
+LEFT JOIN members ON members.conversation_id = c.id
+INNER JOIN message_stats ms ON ms.conversation_id = c.id
+LEFT JOIN messages m ON m.id = ms.last_message_id -- last message may not exists: use left join
+ORDER BY COALESCE(m.sent_at, c.created_at) DESC, c.id DESC
+LIMIT ?2 OFFSET ?3;
@@ -0,0 +1,146 @@
+"""
+Benchmark of the joined conversations query (GET /api/conversation/joined).
+
+Seeds an in-memory SQLite database shaped like the dataset described in README.md (1,000 users, 500
+conversations with 2~10 members, 50 messages per member), then times the previous temp table script and the
+current single query for random users. Only the standard library is needed:
+
+    python3 scripts/bench_joined_conversations.py [--users 100] [--seed 0]
+"""
+
+import argparse
+import random
+import sqlite3
+import statistics
+import time
+from datetime import datetime, timedelta
+from pathlib import Path
+
+SERVER_DIRECTORY = Path(__file__).resolve().parent.parent
+
+# Same schema and query as the server.
+SCHEMA = ''.join(path.read_text() for path in sorted((SERVER_DIRECTORY / 'migrations').glob('*.sql')))
+CURRENT_QUERY = (SERVER_DIRECTORY / 'queries' / 'get_joined_conversations.sql').read_text()
+
+# Query before the rewrite, without its final SELECT.
+PREVIOUS_SCRIPT = """
+DROP TABLE IF EXISTS joined_conversations;
+
+CREATE TEMP TABLE joined_conversations AS
+    SELECT conversations.id, conversations.name
+    FROM conversations
+    INNER JOIN
+        (SELECT conversation_id
+        FROM group_members
+        WHERE username = '{username}') gm
+    ON conversations.id = gm.conversation_id;
+
+DROP TABLE IF EXISTS last_messages_by_conversations;
+
+CREATE TEMP TABLE last_messages_by_conversations AS
+    SELECT jc.id AS conversation_id, json_object('id', messages.id, 'sender_username', sender_username, 'text', text, 'sent_at', MAX(sent_at)) AS message
+    FROM messages
+    INNER JOIN joined_conversations jc ON messages.conversation_id = jc.id
+    GROUP BY jc.id;
+
+DROP TABLE IF EXISTS joined_members;
+
+CREATE TEMP TABLE joined_members AS
+    SELECT jm.conversation_id, users.username, users.nickname, users.profile_picture_filename, jm.joined_at
+    FROM users
+    INNER JOIN
+        (SELECT jc.id AS conversation_id, gm.username, gm.joined_at
+        FROM group_members gm
+        INNER JOIN joined_conversations jc
+        ON gm.conversation_id = jc.id) jm
+    USING (username)
+    WHERE users.username != '{username}';
+"""
+
+PREVIOUS_SELECT = """
+SELECT id, name, members, lmbc.message AS last_message
+FROM
+    (SELECT jc.id,
+            jc.name,
+            json_group_array(json_object('username', jmj.username, 'nickname', jmj.nickname, 'profile_picture_filename', jmj.profile_picture_filename)) AS members
+    FROM joined_conversations AS jc
+    INNER JOIN
+        (SELECT *
+        FROM joined_members
+        ORDER BY joined_at ASC) AS jmj
+    ON jc.id = jmj.conversation_id
+    GROUP BY jc.id)
+LEFT JOIN last_messages_by_conversations AS lmbc ON id = lmbc.conversation_id;
+"""
+
+def seed(database, rng):
+    database.executescript(SCHEMA)
+
+    start = datetime(2023, 10, 1)
+    format_time = lambda time: time.strftime('%Y-%m-%d %H:%M:%S')
+
+    database.executemany("INSERT INTO users (username, encrypted_password, nickname, created_at) VALUES (?, 'encrypted', ?, ?);",
+        [(f'username{i}', f'Nickname {i}', format_time(start)) for i in range(1000)])
+
+    members = []
+    messages = []
+    for conversation_id in range(1, 501):
+        created_at = start + timedelta(seconds=rng.randrange(31 * 24 * 3600))
+        database.execute("INSERT INTO conversations (id, name, created_at) VALUES (?, ?, ?);",
+            (conversation_id, f'Conversation {conversation_id}', format_time(created_at)))
+
+        usernames = rng.sample([f'username{i}' for i in range(1000)], rng.randint(2, 10))
+        for username in usernames:
+            members.append((username, conversation_id, format_time(created_at + timedelta(seconds=rng.randrange(3600)))))
+        for _ in range(50 * len(usernames)):
+            sent_at = created_at + timedelta(seconds=rng.randrange(3600, 31 * 24 * 3600))
+            messages.append((rng.choice(usernames), 'Lorem ipsum dolor sit amet.', format_time(sent_at), conversation_id))
+
+    database.executemany("INSERT INTO group_members (username, conversation_id, joined_at) VALUES (?, ?, ?);", members)
+    # Insert in sending order, as the server does.
+    messages.sort(key=lambda message: message[2])
+    database.executemany("INSERT INTO messages (sender_username, text, sent_at, conversation_id) VALUES (?, ?, ?, ?);", messages)
+    database.commit()
+    database.execute("ANALYZE;")
+
+    return len(members), len(messages)
+
+
+def measure(run, usernames):
+    durations = []
+    for username in usernames:
+        begin = time.perf_counter()
+        run(username)
+        durations.append((time.perf_counter() - begin) * 1000)
+    return statistics.mean(durations), statistics.median(durations), max(durations)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__, formatter_class=argparse.RawDescriptionHelpFormatter)
+    parser.add_argument('--users', type=int, default=100, help='number of users to run the queries for')
+    parser.add_argument('--seed', type=int, default=0)
+    args = parser.parse_args()
+
+    rng = random.Random(args.seed)
+    database = sqlite3.connect(':memory:')
+    member_count, message_count = seed(database, rng)
+    print(f'Seeded 1000 users, 500 conversations, {member_count} group_members, {message_count} messages.')
+
+    usernames = [username for (username,) in database.execute(
+        "SELECT DISTINCT username FROM group_members ORDER BY username;")]
+    usernames = rng.sample(usernames, min(args.users, len(usernames)))
+
+    def run_previous(username):
+        database.executescript(PREVIOUS_SCRIPT.format(username=username))
+        database.execute(PREVIOUS_SELECT).fetchall()
+
+    def run_current(username):
+        database.execute(CURRENT_QUERY, (username, 51, 0)).fetchall()
+
+    for name, run in [('previous (temp tables)', run_previous), ('current (single query)', run_current)]:
+        mean, median, maximum = measure(run, usernames)
+        print(f'{name}: mean {mean:.2f} ms, median {median:.2f} ms, max {maximum:.2f} ms')
+
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,159 @@
+use std::fmt::Display;
+
+use actix_web::http::Method;
+use chrono::NaiveDateTime;
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use sqlx::SqlitePool;
+
+/// Prefix of the tokens, to recognize them (e.g. by secret scanners).
+const TOKEN_PREFIX: &str = "chat_";
+
+/// What a token is allowed to do.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope{
+    /// Read requests (`GET`), and receiving websocket events.
+    Read,
+    /// Other requests, and sending messages through websocket.
+    Write,
+}
+
+impl Scope{
+    /// Scope required by a REST request.
+    pub fn required_by(method: &Method) -> Scope{
+        if method == Method::GET || method == Method::HEAD{
+            Scope::Read
+        }
+        else{
+            Scope::Write
+        }
+    }
+
+    fn as_str(&self) -> &'static str{
+        match self{
+            Scope::Read => "read",
+            Scope::Write => "write",
+        }
+    }
+
+    /// Parse the space separated scopes stored in the database, ignoring the unknown ones.
+    fn parse_all(scopes: &str) -> Vec<Scope>{
+        scopes.split_whitespace()
+            .filter_map(|scope| match scope{
+                "read" => Some(Scope::Read),
+                "write" => Some(Scope::Write),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn join_all(scopes: &[Scope]) -> String{
+        scopes.iter().map(Scope::as_str).collect::<Vec<_>>().join(" ")
+    }
+}
+
+impl Display for Scope{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+        f.write_str(self.as_str())
+    }
+}
+
+/// Personal access token, without its secret.
+#[derive(Serialize, Debug)]
+pub struct ApiToken{
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+/// Owner and scopes of a valid token.
+#[derive(Debug)]
+pub struct TokenGrant{
+    pub id: i64,
+    pub username: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiToken{
+    /// Create a new token for the user, and return it with its secret. Only the hash of the secret is stored, so it
+    /// cannot be shown again.
+    pub async fn create(database: &SqlitePool, username: &str, name: &str, scopes: &[Scope], expires_at: Option<NaiveDateTime>) -> Result<(ApiToken, String), sqlx::Error>{
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let token = format!("{TOKEN_PREFIX}{}", secret.iter().map(|byte| format!("{byte:02x}")).collect::<String>());
+
+        let token_hash = Self::hash(&token);
+        let joined_scopes = Scope::join_all(scopes);
+        let record = sqlx::query!(
+                "INSERT INTO api_tokens (username, name, token_hash, scopes, created_at, expires_at) VALUES (?, ?, ?, ?, DATETIME('NOW'), ?)
+                RETURNING id, created_at;", username, name, token_hash, joined_scopes, expires_at)
+            .fetch_one(database)
+            .await?;
+
+        Ok((ApiToken{
+            id: record.id,
+            name: name.to_owned(),
+            scopes: scopes.to_vec(),
+            created_at: record.created_at,
+            expires_at,
+            last_used_at: None,
+        }, token))
+    }
+
+    /// Get the tokens of the user, including the expired ones, newest first.
+    pub async fn get_all(database: &SqlitePool, username: &str) -> Result<Vec<ApiToken>, sqlx::Error>{
+        Ok(sqlx::query!(
+                "SELECT id, name, scopes, created_at, expires_at, last_used_at
+                FROM api_tokens
+                WHERE username = ?
+                ORDER BY id DESC;", username)
+            .fetch_all(database)
+            .await?
+            .into_iter()
+            .map(|record| ApiToken{
+                id: record.id,
+                name: record.name,
+                scopes: Scope::parse_all(&record.scopes),
+                created_at: record.created_at,
+                expires_at: record.expires_at,
+                last_used_at: record.last_used_at,
+            })
+            .collect())
+    }
+
+    /// Delete the token of the user. Returns `false` if the user has no such token.
+    pub async fn revoke(database: &SqlitePool, username: &str, id: i64) -> Result<bool, sqlx::Error>{
+        Ok(sqlx::query!("DELETE FROM api_tokens WHERE id = ? AND username = ?;", id, username)
+            .execute(database)
+            .await?
+            .rows_affected() > 0)
+    }
+
+    /// Look up an unexpired token by its secret, and record its use.
+    pub async fn authenticate(database: &SqlitePool, token: &str) -> Result<Option<TokenGrant>, sqlx::Error>{
+        let token_hash = Self::hash(token);
+        let record = sqlx::query!(
+                "UPDATE api_tokens
+                SET last_used_at = DATETIME('NOW')
+                WHERE token_hash = ? AND (expires_at IS NULL OR expires_at > DATETIME('NOW'))
+                RETURNING id AS \"id!\", username, scopes;", token_hash)
+            .fetch_optional(database)
+            .await?;
+
+        Ok(record.map(|record| TokenGrant{
+            id: record.id,
+            username: record.username,
+            scopes: Scope::parse_all(&record.scopes),
+        }))
+    }
+
+    /// Tokens are random 256 bit secrets, so a fast hash is enough (unlike passwords).
+    fn hash(token: &str) -> String{
+        format!("{:x}", Sha256::digest(token.as_bytes()))
+    }
+}
@@ -0,0 +1,153 @@
+//! Extractors authenticating the requests.
+//!
+//! Handlers take [`AuthenticatedUser`] to require a logged in user, or [`ConversationMember`] to also require the
+//! membership of the `{conversation_id}` in the path. Requests failing the checks are rejected with
+//! [`ApiError`] before reaching the handler. What a member is allowed to do depends on their role, which the handlers
+//! check with [`ConversationMember::require`].
+//!
+//! A user is either logged in by the cookie session (set by `POST /api/user/login`, and valid until the password
+//! changes), or by an API token in the `Authorization: Bearer {token}` header. A request with the header is never
+//! authenticated by the session, and the token must have the scope required by the request method (see
+//! [`Scope::required_by`]).
+
+use std::{future::Future, pin::Pin};
+
+use actix_session::SessionExt;
+use actix_web::{FromRequest, HttpRequest, dev::Payload, web, http::header};
+
+use crate::{AppState, api::{ApiError, user::User, conversation::{get_member_role, MemberRole, Permission}, api_token::{ApiToken, Scope}}};
+
+/// Logged in user of the request.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser{
+    pub username: String,
+    pub credential: Credential,
+}
+
+/// How the user of the request is logged in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credential{
+    Session,
+    ApiToken{ id: i64, scopes: Vec<Scope> },
+}
+
+/// Logged in user of the request, who is a member of the `{conversation_id}` in the path.
+#[derive(Debug, Clone)]
+pub struct ConversationMember{
+    pub username: String,
+    pub conversation_id: i64,
+    pub role: MemberRole,
+}
+
+impl AuthenticatedUser{
+    /// Whether the user is allowed to do what the scope grants. Session users are allowed to do everything.
+    pub fn has_scope(&self, scope: Scope) -> bool{
+        match &self.credential{
+            Credential::Session => true,
+            Credential::ApiToken{ scopes, .. } => scopes.contains(&scope),
+        }
+    }
+
+    /// Reject the request unless the user is logged in by the session, e.g. to manage the API tokens.
+    pub fn require_session(&self) -> Result<(), ApiError>{
+        match self.credential{
+            Credential::Session => Ok(()),
+            Credential::ApiToken{ .. } => Err(ApiError::Forbidden("This request requires a browser session.".to_owned())),
+        }
+    }
+
+    fn authenticate(req: &HttpRequest) -> Pin<Box<dyn Future<Output = Result<Self, ApiError>>>>{
+        let bearer_token = match req.headers().get(header::AUTHORIZATION){
+            None => None,
+            Some(value) => match value.to_str().ok().and_then(|value| value.strip_prefix("Bearer ")){
+                Some(token) => Some(token.trim().to_owned()),
+                None => return Box::pin(std::future::ready(Err(ApiError::Unauthorized))),
+            },
+        };
+
+        let session = req.get_session();
+        let required_scope = Scope::required_by(req.method());
+        let app_state = req.app_data::<web::Data<AppState>>().cloned();
+
+        Box::pin(async move{
+            let app_state = app_state.ok_or_else(|| ApiError::internal("AppState is not configured."))?;
+
+            let Some(token) = bearer_token else{
+                let Some((username, session_epoch)) = User::get_username_from_session(&session)? else{
+                    return Err(ApiError::Unauthorized);
+                };
+
+                // VALIDATION: Session must not be invalidated by a password change, nor the user deleted.
+                let current_session_epoch = sqlx::query!("SELECT session_epoch FROM users WHERE username = ?;", username)
+                    .fetch_optional(&app_state.database)
+                    .await?
+                    .map(|record| record.session_epoch);
+                if current_session_epoch != Some(session_epoch){
+                    User::expire_session(session);
+                    return Err(ApiError::Unauthorized);
+                }
+
+                return Ok(AuthenticatedUser{ username, credential: Credential::Session });
+            };
+
+            // VALIDATION: Token must exist, and not be expired.
+            let grant = ApiToken::authenticate(&app_state.database, &token).await?.ok_or(ApiError::Unauthorized)?;
+
+            // VALIDATION: Token must have the scope required by the request.
+            if !grant.scopes.contains(&required_scope){
+                return Err(ApiError::InsufficientScope(required_scope));
+            }
+
+            Ok(AuthenticatedUser{
+                username: grant.username,
+                credential: Credential::ApiToken{ id: grant.id, scopes: grant.scopes },
+            })
+        })
+    }
+}
+
+impl ConversationMember{
+    /// Reject the request unless the role of the member allows the action.
+    pub fn require(&self, permission: Permission) -> Result<(), ApiError>{
+        if !self.role.allows(permission){
+            return Err(ApiError::Forbidden(format!("Your role ({}) is not allowed to {permission}.", self.role)));
+        }
+        Ok(())
+    }
+}
+
+impl FromRequest for AuthenticatedUser{
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future{
+        Self::authenticate(req)
+    }
+}
+
+impl FromRequest for ConversationMember{
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future{
+        let user = AuthenticatedUser::authenticate(req);
+        let conversation_id = req.match_info().get("conversation_id")
+            .ok_or_else(|| ApiError::internal("ConversationMember is used without {conversation_id} in the path."))
+            .and_then(|conversation_id| conversation_id.parse::<i64>()
+                .map_err(|_| ApiError::InvalidRequest("Conversation id should be an integer.".to_owned())));
+        let app_state = req.app_data::<web::Data<AppState>>().cloned();
+
+        Box::pin(async move{
+            let AuthenticatedUser{ username, .. } = user.await?;
+            let conversation_id = conversation_id?;
+            let app_state = app_state.ok_or_else(|| ApiError::internal("AppState is not configured."))?;
+
+            // VALIDATION: Check if user joined to the given conversation.
+            let Some(role) = get_member_role(&app_state.database, &username, conversation_id).await? else{
+                return Err(ApiError::NotMember);
+            };
+
+            Ok(ConversationMember{ username, conversation_id, role })
+        })
+    }
+}
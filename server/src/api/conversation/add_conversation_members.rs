@@ -0,0 +1,85 @@
+/*
+ * Add users to the conversation.
+ *
+ * Request:
+ * POST /api/conversation/{conversation_id}/members
+ * {
+ *     "usernames": ["user3", "user4"]
+ * }
+ *
+ * Response:
+ * HTTP 204 No Content
+ *
+ * Only the owner and the admins can add members, who join with the `member` role. A system message
+ * ("User 1 added User 3") is written for each added user, and the new members receive a `conversation_added` event.
+ */
+
+use actix_web::{post, web, Responder, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    AppState,
+    api::{ApiError, ConversationMember, message::Message},
+    websocket::{server::{SendToConversation, SendToUsers}, protocol::Event},
+};
+
+use super::{get_nickname, Permission};
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    usernames: Vec<String>,
+}
+
+#[post("/{conversation_id}/members")]
+async fn handler(member: ConversationMember, request: web::Json<Request>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    member.require(Permission::AddMembers)?;
+
+    let mut usernames = request.into_inner().usernames;
+    usernames.sort();
+    usernames.dedup();
+
+    // VALIDATION: At least one user must be given.
+    if usernames.is_empty(){
+        return Err(ApiError::InvalidRequest("Give at least one user to add.".to_owned()));
+    }
+
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    let actor_nickname = get_nickname(&mut *tx, &member.username).await?;
+    let mut messages = Vec::new();
+    for username in &usernames{
+        sqlx::query!("INSERT INTO group_members (username, conversation_id, joined_at) VALUES (?, ?, DATETIME('NOW'));", username, member.conversation_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| match err{
+                sqlx::Error::Database(err) if err.kind() == sqlx::error::ErrorKind::ForeignKeyViolation => {
+                    ApiError::InvalidRequest(format!("User {username} does not exist."))
+                },
+                sqlx::Error::Database(err) if err.kind() == sqlx::error::ErrorKind::UniqueViolation => {
+                    ApiError::Conflict(format!("User {username} is already a member."))
+                },
+                _ => err.into()
+            })?;
+
+        let nickname = get_nickname(&mut *tx, username).await?;
+        let text = format!("{actor_nickname} added {nickname}");
+        messages.push(Message::insert_system(&mut *tx, &member.username, &text, member.conversation_id).await?);
+    }
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    for message in messages{
+        app_state.websocket_server.do_send(SendToConversation {
+            conversation_id: member.conversation_id,
+            event: Event::Message { conversation_id: member.conversation_id, message },
+        });
+    }
+    app_state.websocket_server.do_send(SendToUsers {
+        usernames,
+        event: Event::ConversationAdded { conversation_id: member.conversation_id },
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,75 @@
+/*
+ * Appoint a member as an admin, or dismiss an admin. Only the owner can change the roles.
+ *
+ * Request:
+ * PUT /api/conversation/{conversation_id}/members/{username}/role
+ * {
+ *     "role": "admin" // or "member"
+ * }
+ *
+ * Response:
+ * HTTP 204 No Content
+ *
+ * A system message ("User 1 made User 3 an admin") is written if the role changed. To make someone the owner, use
+ * `POST /api/conversation/{conversation_id}/transfer_ownership`.
+ */
+
+use actix_web::{put, web, Responder, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    AppState,
+    api::{ApiError, ConversationMember, message::Message},
+    websocket::{server::SendToConversation, protocol::Event},
+};
+
+use super::{get_nickname, get_member_role, MemberRole, Permission};
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    role: MemberRole,
+}
+
+#[put("/{conversation_id}/members/{username}/role")]
+async fn handler(member: ConversationMember, path: web::Path<(i64, String)>, request: web::Json<Request>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    let (_, username) = path.into_inner();
+    member.require(Permission::ChangeRoles)?;
+
+    // VALIDATION: Ownership is only transferred.
+    if request.role == MemberRole::Owner{
+        return Err(ApiError::InvalidRequest("Transfer the ownership to make someone the owner.".to_owned()));
+    }
+
+    // VALIDATION: Owner cannot change their own role.
+    if username == member.username{
+        return Err(ApiError::InvalidRequest("You cannot change your own role.".to_owned()));
+    }
+
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    let Some(role) = get_member_role(&mut *tx, &username, member.conversation_id).await? else{
+        return Err(ApiError::NotFound(format!("User {username} is not a member of this conversation.")));
+    };
+    if role == request.role{
+        return Ok(HttpResponse::NoContent().finish());
+    }
+
+    sqlx::query!("UPDATE group_members SET role = ? WHERE username = ? AND conversation_id = ?;", request.role, username, member.conversation_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let article = if request.role == MemberRole::Admin{ "an" } else{ "a" };
+    let text = format!("{} made {} {article} {}", get_nickname(&mut *tx, &member.username).await?, get_nickname(&mut *tx, &username).await?, request.role);
+    let message = Message::insert_system(&mut *tx, &member.username, &text, member.conversation_id).await?;
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    app_state.websocket_server.do_send(SendToConversation {
+        conversation_id: member.conversation_id,
+        event: Event::Message { conversation_id: member.conversation_id, message },
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
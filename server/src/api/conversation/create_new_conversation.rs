@@ -1,10 +1,9 @@
-use actix_session::Session;
-use actix_web::{post, web, Responder, HttpResponse, Error};
-use futures::future::join_all;
+use actix_web::{post, web, Responder, HttpResponse};
 use serde::Deserialize;
-use sqlx_core::any::AnyConnectionBackend;
 
-use crate::{AppState, api::{map_internal_error, user::User}};
+use crate::{AppState, api::{ApiError, AuthenticatedUser}, websocket::{server::SendToUsers, protocol::Event}};
+
+use super::MemberRole;
 
 #[derive(Deserialize, Debug)]
 pub struct Request{
@@ -13,63 +12,51 @@ pub struct Request{
 }
 
 #[post("/")]
-pub async fn handler(request: web::Json<Request>, app_state: web::Data<AppState>, session: Session) -> Result<impl Responder, Error>{
-    // VALIDATION: User must be logged in.
-    let username = match User::get_username_from_session(session){
-        Some(username) => username,
-        None => return Ok(HttpResponse::Unauthorized().finish())
-    };
-
+pub async fn handler(user: AuthenticatedUser, request: web::Json<Request>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
     // VALIDATION: Check if user is in the members list.
-    if !request.members.contains(&username){
-        return Ok(HttpResponse::Forbidden().body("You must be in the members list."));
+    if !request.members.contains(&user.username){
+        return Err(ApiError::Forbidden("You must be in the members list.".to_owned()));
     }
 
     // VALIDATION: User cannot create a conversation with only himself.
     if request.members.len() == 1{
-        return Ok(HttpResponse::BadRequest().body("You cannot create a conversation with only yourself."));
+        return Err(ApiError::InvalidRequest("You cannot create a conversation with only yourself.".to_owned()));
     }
 
     // TRANSACTION START.
-    let mut tx = app_state.database.acquire()
-        .await
-        .map_err(map_internal_error)?;
+    let mut tx = app_state.database.begin().await?;
     
     // Create new conversation from conversations table.
     let conversation_id = sqlx::query!("INSERT INTO conversations (name, created_at) VALUES (?, DATETIME('NOW')) RETURNING id;", request.conversation_name)
-        .fetch_one(&app_state.database)
-        .await
-        .map_err(map_internal_error)?
+        .fetch_one(&mut *tx)
+        .await?
         .id;
 
-    // Add conversation members into group_members table.
-    let query_futures = request.members.iter().map(|member_username| {
-        async {
-            sqlx::query!("INSERT INTO group_members (username, conversation_id, joined_at) VALUES (?, ?, DATETIME('NOW'));", *member_username, conversation_id)
-                .execute(&app_state.database)
-                .await
-        }
-    });
-
-    for result in join_all(query_futures).await{
-        match result{
-            Ok(_) => {},
-            Err(err) => {
-                // Rollback the transaction.
-                tx.rollback().await.map_err(map_internal_error)?;
-
-                match err{
-                    sqlx::Error::Database(err) if err.kind() == sqlx::error::ErrorKind::ForeignKeyViolation => {
-                        return Ok(HttpResponse::BadRequest().body("The request contains inexisting conversation member."))
-                    },
-                    _ => return Ok(HttpResponse::InternalServerError().body("Internal server error."))
-                }
-            }
-        }
+    // Add conversation members into group_members table. The creator owns the conversation.
+    for member_username in &request.members{
+        let role = if *member_username == user.username{ MemberRole::Owner } else{ MemberRole::Member };
+        sqlx::query!("INSERT INTO group_members (username, conversation_id, joined_at, role) VALUES (?, ?, DATETIME('NOW'), ?);", member_username, conversation_id, role)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| match err{
+                sqlx::Error::Database(err) if err.kind() == sqlx::error::ErrorKind::ForeignKeyViolation => {
+                    ApiError::InvalidRequest("The request contains inexisting conversation member.".to_owned())
+                },
+                sqlx::Error::Database(err) if err.kind() == sqlx::error::ErrorKind::UniqueViolation => {
+                    ApiError::InvalidRequest("The request contains duplicated conversation member.".to_owned())
+                },
+                _ => err.into()
+            })?;
     }
 
-    tx.commit().await.map_err(map_internal_error)?;
+    tx.commit().await?;
     // TRANSACTION END.
 
+    // Let every device of the members know the new conversation, so they can subscribe to it.
+    app_state.websocket_server.do_send(SendToUsers {
+        usernames: request.into_inner().members,
+        event: Event::ConversationAdded { conversation_id }
+    });
+
     Ok(HttpResponse::Ok().finish())
 }
\ No newline at end of file
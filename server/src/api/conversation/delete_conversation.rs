@@ -0,0 +1,56 @@
+/*
+ * Delete the conversation with its messages. Only the owner can delete it.
+ *
+ * Request:
+ * DELETE /api/conversation/{conversation_id}
+ *
+ * Response:
+ * HTTP 204 No Content
+ *
+ * Sessions of every member receive a `conversation_removed` event.
+ */
+
+use actix_web::{delete, web, Responder, HttpResponse};
+
+use crate::{
+    AppState,
+    api::{ApiError, ConversationMember, user::remove_profile_picture},
+    websocket::{server::SendToUsers, protocol::Event},
+};
+
+use super::Permission;
+
+#[delete("/{conversation_id}")]
+async fn handler(member: ConversationMember, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    member.require(Permission::DeleteConversation)?;
+
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    let usernames = sqlx::query!("SELECT username FROM group_members WHERE conversation_id = ?;", member.conversation_id)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|record| record.username)
+        .collect();
+
+    // Memberships and messages are deleted by `ON DELETE CASCADE`.
+    let avatar_filename = sqlx::query!("DELETE FROM conversations WHERE id = ? RETURNING avatar_filename;", member.conversation_id)
+        .fetch_one(&mut *tx)
+        .await?
+        .avatar_filename;
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    if let Some(filename) = avatar_filename{
+        remove_profile_picture(&filename, &app_state);
+    }
+
+    app_state.websocket_server.do_send(SendToUsers {
+        usernames,
+        event: Event::ConversationRemoved { conversation_id: member.conversation_id },
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
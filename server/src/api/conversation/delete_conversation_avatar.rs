@@ -0,0 +1,43 @@
+/*
+ * Remove the avatar of the conversation. Only the owner and the admins can remove it.
+ *
+ * Request:
+ * DELETE /api/conversation/{conversation_id}/avatar
+ *
+ * Response:
+ * HTTP 200 OK
+ * {
+ *     "id": 1,
+ *     "name": "Conversation 1",
+ *     "topic": null,
+ *     "description": null,
+ *     "avatar_filename": null
+ * }
+ *
+ * A system message ("User 1 removed the conversation picture") is written if there was an avatar, and the subscribed
+ * sessions receive a `conversation_updated` event.
+ */
+
+use actix_web::{delete, web, Responder, HttpResponse};
+
+use crate::{AppState, api::{ApiError, ConversationMember, user::remove_profile_picture}};
+
+use super::{replace_conversation_avatar, notify_conversation_updated, ConversationInfo, Permission};
+
+#[delete("/{conversation_id}/avatar")]
+async fn handler(member: ConversationMember, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    member.require(Permission::Edit)?;
+
+    let (previous_filename, message) = replace_conversation_avatar(&member, None, &app_state).await?;
+    let Some(message) = message else{
+        let conversation = ConversationInfo::get(&app_state.database, member.conversation_id).await?;
+        return Ok(HttpResponse::Ok().json(conversation));
+    };
+
+    if let Some(previous_filename) = previous_filename{
+        remove_profile_picture(&previous_filename, &app_state);
+    }
+
+    let conversation = notify_conversation_updated(member.conversation_id, vec![message], &app_state).await?;
+    Ok(HttpResponse::Ok().json(conversation))
+}
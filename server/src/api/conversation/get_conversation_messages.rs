@@ -1,31 +1,123 @@
-use actix_session::Session;
-use actix_web::{get, web, Responder, HttpResponse, Error};
+/*
+ * Get a page of messages in the conversation, oldest first.
+ *
+ * Request:
+ * GET /api/conversation/{conversation_id}/messages?before={message_id}&limit={limit}
+ * GET /api/conversation/{conversation_id}/messages?after={message_id}&limit={limit}
+ *
+ * Without cursor, the latest messages are returned. `before` and `after` cannot be used together. `limit` is 50
+ * by default, and at most 200.
+ *
+ * Response:
+ * HTTP 200 OK
+ * {
+ *     "messages": [
+ *         {
+ *             "id": 1,
+ *             "sender_username": "user1", // null if the sender deleted their account.
+ *             "text": "Hello!",
+ *             "sent_at": "2021-01-01T00:00:00",
+ *             "kind": "text" // "system" for the events of the conversation, e.g. "User 1 added User 2".
+ *         },
+ *         ...
+ *     ],
+ *     "prev_cursor": 1, // Use as `before` to get the older page, null if there is no older message.
+ *     "next_cursor": 50 // Use as `after` to get the newer page, null if there is no newer message.
+ * }
+ */
 
-use crate::{AppState, api::{user::User, conversation::is_user_joined_in_conversation, map_internal_error}};
+use actix_web::{get, web, Responder, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::{AppState, api::{ApiError, ConversationMember}};
 use crate::api::message::Message;
 
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize, Debug)]
+pub struct Query{
+    before: Option<i64>,
+    after: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+struct Page{
+    messages: Vec<Message>,
+    prev_cursor: Option<i64>,
+    next_cursor: Option<i64>,
+}
+
 #[get("/{conversation_id}/messages")]
-async fn handler(path: web::Path<i64>, app_state: web::Data<AppState>, session: Session) -> Result<impl Responder, Error>{
-    // VALIDATION: User must be logged in.
-    let username = match User::get_username_from_session(session){
-        Some(username) => username,
-        None => return Ok(HttpResponse::Unauthorized().finish())
+async fn handler(member: ConversationMember, query: web::Query<Query>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    let conversation_id = member.conversation_id;
+
+    // VALIDATION: Check the cursors and limit.
+    if query.before.is_some() && query.after.is_some(){
+        return Err(ApiError::InvalidRequest("Use either before or after cursor, not both.".to_owned()));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit){
+        return Err(ApiError::InvalidRequest(format!("Limit should be between 1 and {MAX_LIMIT}.")));
+    }
+
+    // Fetch one more message than the limit, to know if there is more in that direction.
+    let page = match query.after{
+        Some(after) => {
+            let mut messages = Message::get_after(&app_state.database, conversation_id, after, limit + 1).await?;
+            let has_newer = messages.len() as i64 > limit;
+            messages.truncate(limit as usize);
+
+            let has_older = has_message_up_to(&app_state.database, conversation_id, after).await?;
+            make_page(messages, has_older, has_newer, &query)
+        }
+        None => {
+            let before = query.before.unwrap_or(i64::MAX);
+            let mut messages = Message::get_before(&app_state.database, conversation_id, before, limit + 1).await?;
+            let has_older = messages.len() as i64 > limit;
+            if has_older{
+                messages.remove(0);
+            }
+
+            let has_newer = match query.before{
+                Some(before) => has_message_from(&app_state.database, conversation_id, before).await?,
+                None => false,
+            };
+            make_page(messages, has_older, has_newer, &query)
+        }
     };
 
-    // VALIDATION: Check if user joined to the given conversation.
-    let conversation_id = path.into_inner();
-    if !is_user_joined_in_conversation(&app_state.database, &username, conversation_id).await
-        .map_err(map_internal_error)?{
-        return Ok(HttpResponse::Forbidden().body("You are not joined to this conversation."))
+    Ok(HttpResponse::Ok().json(page))
+}
+
+/// An empty page (e.g. after the latest message) has no message to take the cursors from, so they are derived from
+/// the request cursor: the messages up to `after` are `before` `after + 1`, and the messages from `before` are `after`
+/// `before - 1`.
+fn make_page(messages: Vec<Message>, has_older: bool, has_newer: bool, query: &Query) -> Page{
+    let first_id = messages.first().map(|message| message.id).or(query.after.map(|after| after.saturating_add(1)));
+    let last_id = messages.last().map(|message| message.id).or(query.before.map(|before| before.saturating_sub(1)));
+    Page{
+        prev_cursor: first_id.filter(|_| has_older),
+        next_cursor: last_id.filter(|_| has_newer),
+        messages,
     }
+}
 
-    let messages = sqlx::query_as!(Message, 
-            "SELECT id, sender_username, text, sent_at 
-            FROM messages 
-            WHERE conversation_id = ?
-            ORDER BY sent_at ASC;", conversation_id)
-        .fetch_all(&app_state.database)
-        .await.unwrap();
+/// Whether the conversation has a message with an id up to the cursor, inclusive. The cursor is compared as is, so
+/// that any `i64` query value is fine.
+async fn has_message_up_to(database: &SqlitePool, conversation_id: i64, cursor: i64) -> Result<bool, sqlx::Error>{
+    Ok(sqlx::query!("SELECT 1 AS x FROM messages WHERE conversation_id = ? AND id <= ? LIMIT 1;", conversation_id, cursor)
+        .fetch_optional(database)
+        .await?
+        .is_some())
+}
 
-    Ok(HttpResponse::Ok().json(messages))
-}
\ No newline at end of file
+/// Whether the conversation has a message with an id from the cursor, inclusive.
+async fn has_message_from(database: &SqlitePool, conversation_id: i64, cursor: i64) -> Result<bool, sqlx::Error>{
+    Ok(sqlx::query!("SELECT 1 AS x FROM messages WHERE conversation_id = ? AND id >= ? LIMIT 1;", conversation_id, cursor)
+        .fetch_optional(database)
+        .await?
+        .is_some())
+}
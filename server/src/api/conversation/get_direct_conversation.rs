@@ -0,0 +1,108 @@
+/*
+ * Get the direct (one-to-one) conversation between the session user and another user, creating it if it doesn't
+ * exist yet.
+ *
+ * Request:
+ * POST /api/conversation/direct
+ * {
+ *     "username": "user2"
+ * }
+ *
+ * Response:
+ * HTTP 201 Created, or HTTP 200 OK if it already existed
+ * {
+ *     "id": 4,
+ *     "kind": "direct",
+ *     "name": "User 1, User 2",
+ *     "topic": null,
+ *     "description": null,
+ *     "avatar_filename": null
+ * }
+ *
+ * A pair of users has at most one direct conversation. Participants who left it join it again, and receive a
+ * `conversation_added` event like the participants of a new one.
+ */
+
+use actix_web::{post, web, Responder, HttpResponse};
+use serde::Deserialize;
+
+use crate::{AppState, api::{ApiError, AuthenticatedUser}, websocket::{server::SendToUsers, protocol::Event}};
+
+use super::{get_nickname, ConversationInfo};
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    username: String,
+}
+
+#[post("/direct")]
+async fn handler(user: AuthenticatedUser, request: web::Json<Request>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    // VALIDATION: User cannot create a conversation with only himself.
+    if request.username == user.username{
+        return Err(ApiError::InvalidRequest("You cannot create a conversation with only yourself.".to_owned()));
+    }
+
+    // Participants are ordered, so that both of them get the same conversation.
+    let participants = if user.username < request.username{
+        [user.username.as_str(), request.username.as_str()]
+    }
+    else{
+        [request.username.as_str(), user.username.as_str()]
+    };
+    let dm_key = participants.join(":");
+
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    // VALIDATION: Other user must exist.
+    let other_nickname = sqlx::query!("SELECT nickname FROM users WHERE username = ?;", request.username)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("User {} does not exist.", request.username)))?
+        .nickname;
+    let name = format!("{}, {other_nickname}", get_nickname(&mut *tx, &user.username).await?);
+
+    let created_id = sqlx::query!("INSERT INTO conversations (kind, dm_key, name, created_at) VALUES ('direct', ?, ?, DATETIME('NOW'))
+        ON CONFLICT (dm_key) WHERE dm_key IS NOT NULL DO NOTHING
+        RETURNING id AS \"id!\";", dm_key, name)
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|record| record.id);
+    let conversation_id = match created_id{
+        Some(id) => id,
+        None => sqlx::query!("SELECT id FROM conversations WHERE dm_key = ?;", dm_key)
+            .fetch_one(&mut *tx)
+            .await?
+            .id,
+    };
+
+    // Join the participants who are not members, i.e. both of them for a new conversation, or those who left it.
+    let mut joined_usernames = Vec::new();
+    for username in participants{
+        let joined = sqlx::query!("INSERT INTO group_members (username, conversation_id, joined_at) VALUES (?, ?, DATETIME('NOW'))
+            ON CONFLICT (username, conversation_id) DO NOTHING;", username, conversation_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() > 0;
+        if joined{
+            joined_usernames.push(username.to_owned());
+        }
+    }
+
+    let conversation = ConversationInfo::get(&mut *tx, conversation_id)
+        .await?
+        .ok_or_else(|| ApiError::internal("Direct conversation is not found right after its creation."))?;
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    if !joined_usernames.is_empty(){
+        app_state.websocket_server.do_send(SendToUsers {
+            usernames: joined_usernames,
+            event: Event::ConversationAdded { conversation_id },
+        });
+    }
+
+    let mut response = if created_id.is_some(){ HttpResponse::Created() } else{ HttpResponse::Ok() };
+    Ok(response.json(conversation))
+}
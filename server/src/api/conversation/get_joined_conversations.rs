@@ -1,118 +1,119 @@
 /*
- * Get session user's joined conversations.
+ * Get session user's joined conversations, most recently active first.
  * 
  * Request:
- * POST /api/conversation/joined
+ * GET /api/conversation/joined?limit={limit}&offset={offset}
+ *
+ * `limit` is 50 by default, and at most 200. `offset` is 0 by default.
  * 
  * Response:
  * HTTP 200 OK
- * [
- *     {
- *         "id": 1,
- *         "name": "Conversation 1",
- *         "members": [
- *             {
- *                 "username": "user1",
- *                 "nickname": "User 1",
- *                 "profile_picture_filename": "user1.png"
- *             },
- *             {
- *                 "username": "user2",
- *                 "nickname": "User 2",
- *                 "profile_picture_filename": "user2.png"
- *             },
- *             ...
- *         ],
- *         "last_message": {
+ * {
+ *     "conversations": [
+ *         {
  *             "id": 1,
- *             "sender_username": "user1",
- *             "text": "Hello!",
- *             "sent_at": "2021-01-01 00:00:00"
- *         }
- *     },
- *     ...
- * ]
+ *             "kind": "group", // or "direct"
+ *             "name": "Conversation 1", // Nickname of the other participant for the direct conversations.
+ *             "topic": "Trip to Jeju", // null if not set.
+ *             "avatar_filename": null, // Served by `GET /api/user/profile_picture/{filename}`, null if not set.
+ *             "members": [ // Except the session user, in the order they joined.
+ *                 {
+ *                     "username": "user1",
+ *                     "nickname": "User 1",
+ *                     "profile_picture_filename": "user1.png"
+ *                 },
+ *                 {
+ *                     "username": "user2",
+ *                     "nickname": "User 2",
+ *                     "profile_picture_filename": "user2.png"
+ *                 },
+ *                 ...
+ *             ],
+ *             "last_message": { // null if there is no message yet.
+ *                 "id": 1,
+ *                 "sender_username": "user1", // null if the sender deleted their account.
+ *                 "text": "Hello!",
+ *                 "sent_at": "2021-01-01 00:00:00",
+ *                 "kind": "text"
+ *             },
+ *             "unread_count": 3 // Messages of the other members after the last read one.
+ *         },
+ *         ...
+ *     ],
+ *     "next_offset": 50 // Use as `offset` to get the next page, null if there is no more conversation.
+ * }
+ *
+ * Conversations are sorted by their last message, or their creation if there is no message yet. A direct conversation
+ * left by the other participant keeps the name it was created with.
  */
 
-use actix_session::Session;
-use actix_web::{get, web, Responder, HttpResponse, Error};
+use actix_web::{get, web, Responder, HttpResponse};
 use serde::{Serialize, Deserialize};
 use sqlx::types::Json;
 
-use crate::{api::{user::User, map_internal_error}, AppState};
+use crate::{api::{user::User, ApiError, AuthenticatedUser, message::MessageKind}, AppState};
+
+use super::ConversationKind;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize, Debug)]
+pub struct Query{
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Message{
     id: i64,
-    sender_username: String,
+    sender_username: Option<String>,
     text: String,
-    sent_at: String
+    sent_at: String,
+    kind: MessageKind,
 }
 
-#[derive(sqlx::FromRow, Serialize, Debug)]
+#[derive(Serialize, Debug)]
 struct JoinedConversation{
     id: i64,
+    kind: ConversationKind,
     name: String,
+    topic: Option<String>,
+    avatar_filename: Option<String>,
     members: Json<Vec<User>>,
-    last_message: Option<Json<Message>>
+    last_message: Option<Json<Message>>,
+    unread_count: i64,
 }
 
-#[get("/joined")]
-pub async fn handler(app_state: web::Data<AppState>, session: Session) -> Result<impl Responder, Error>{
-    let username = match User::get_username_from_session(session){
-        Some(username) => username,
-        None => return Ok(HttpResponse::Unauthorized().finish())
-    };
-
-    let result = sqlx::query_as::<_, JoinedConversation>(
-        "DROP TABLE IF EXISTS joined_conversations;
-        
-        CREATE TEMP TABLE joined_conversations AS
-            SELECT conversations.id, conversations.name
-            FROM conversations
-            INNER JOIN
-                (SELECT conversation_id 
-                FROM group_members
-                WHERE username = $1) gm
-            ON conversations.id = gm.conversation_id;
+#[derive(Serialize, Debug)]
+struct Page{
+    conversations: Vec<JoinedConversation>,
+    next_offset: Option<i64>,
+}
 
-        DROP TABLE IF EXISTS last_messages_by_conversations;
-        
-        CREATE TEMP TABLE last_messages_by_conversations AS
-            SELECT jc.id AS conversation_id, json_object('id', messages.id, 'sender_username', sender_username, 'text', text, 'sent_at', MAX(sent_at)) AS message
-            FROM messages
-            INNER JOIN joined_conversations jc ON messages.conversation_id = jc.id
-            GROUP BY jc.id;
+#[get("/joined")]
+pub async fn handler(user: AuthenticatedUser, query: web::Query<Query>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    // VALIDATION: Check the limit and offset.
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit){
+        return Err(ApiError::InvalidRequest(format!("Limit should be between 1 and {MAX_LIMIT}.")));
+    }
+    let offset = query.offset.unwrap_or(0);
+    if offset < 0{
+        return Err(ApiError::InvalidRequest("Offset should not be negative.".to_owned()));
+    }
 
-        DROP TABLE IF EXISTS joined_members;
-            
-        CREATE TEMP TABLE joined_members AS
-            SELECT jm.conversation_id, users.username, users.nickname, users.profile_picture_filename, jm.joined_at
-            FROM users
-            INNER JOIN
-                (SELECT jc.id AS conversation_id, gm.username, gm.joined_at
-                FROM group_members gm
-                INNER JOIN joined_conversations jc
-                ON gm.conversation_id = jc.id) jm
-            USING (username)
-            WHERE users.username != $1;
-            
-        SELECT id, name, members, lmbc.message AS last_message
-        FROM
-            (SELECT jc.id, 
-                    jc.name, 
-                    json_group_array(json_object('username', jmj.username, 'nickname', jmj.nickname, 'profile_picture_filename', jmj.profile_picture_filename)) AS members
-            FROM joined_conversations AS jc
-            INNER JOIN 
-                (SELECT *
-                FROM joined_members
-                ORDER BY joined_at ASC) AS jmj -- joined_members_json
-            ON jc.id = jmj.conversation_id
-            GROUP BY jc.id)
-        LEFT JOIN last_messages_by_conversations AS lmbc ON id = lmbc.conversation_id; -- last message may not exists: use left join")
-        .bind(username)
+    // Fetch one more conversation than the limit, to know if there is a next page.
+    let fetch_limit = limit + 1;
+    let mut conversations = sqlx::query_file_as!(JoinedConversation, "queries/get_joined_conversations.sql", user.username, fetch_limit, offset)
         .fetch_all(&app_state.database)
-        .await.map_err(map_internal_error)?;
+        .await?;
+
+    let has_next = conversations.len() as i64 > limit;
+    conversations.truncate(limit as usize);
 
-    Ok(HttpResponse::Ok().json(result))
-}
\ No newline at end of file
+    Ok(HttpResponse::Ok().json(Page{
+        conversations,
+        next_offset: has_next.then_some(offset + limit),
+    }))
+}
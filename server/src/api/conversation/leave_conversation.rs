@@ -0,0 +1,70 @@
+/*
+ * Leave the conversation.
+ *
+ * Request:
+ * POST /api/conversation/{conversation_id}/leave
+ *
+ * Response:
+ * HTTP 204 No Content
+ *
+ * A system message ("User 1 left") is written, and the sessions of the session user receive a
+ * `conversation_removed` event. When the owner leaves, the earliest admin (or member, if there is no admin) becomes
+ * the owner. When the last member leaves, the conversation is deleted with its messages.
+ */
+
+use actix_web::{post, web, Responder, HttpResponse};
+
+use crate::{
+    AppState,
+    api::{ApiError, ConversationMember, message::Message, user::remove_profile_picture},
+    websocket::{server::{SendToConversation, SendToUsers}, protocol::Event},
+};
+
+use super::{get_nickname, delete_conversation_if_empty, assign_successor_owner};
+
+#[post("/{conversation_id}/leave")]
+async fn handler(member: ConversationMember, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    let conversation_id = member.conversation_id;
+
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    let nickname = get_nickname(&mut *tx, &member.username).await?;
+    sqlx::query!("DELETE FROM group_members WHERE username = ? AND conversation_id = ?;", member.username, conversation_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let mut messages = Vec::new();
+    let deleted = delete_conversation_if_empty(&mut *tx, conversation_id).await?;
+    if deleted.is_none(){
+        messages.push(Message::insert_system(&mut *tx, &member.username, &format!("{nickname} left"), conversation_id).await?);
+
+        messages.extend(assign_successor_owner(&mut tx, conversation_id).await?);
+    }
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    for message in messages{
+        app_state.websocket_server.do_send(SendToConversation {
+            conversation_id,
+            event: Event::Message { conversation_id, message },
+        });
+    }
+    // Conversation is deleted: unsubscribe everyone.
+    if let Some(deleted) = deleted{
+        if let Some(filename) = deleted.avatar_filename{
+            remove_profile_picture(&filename, &app_state);
+        }
+        app_state.websocket_server.do_send(SendToConversation {
+            conversation_id,
+            event: Event::ConversationRemoved { conversation_id },
+        });
+    }
+    app_state.websocket_server.do_send(SendToUsers {
+        usernames: vec![member.username],
+        event: Event::ConversationRemoved { conversation_id },
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,39 @@
+/*
+ * Mark the messages in the conversation as read by the session user, up to the given message.
+ *
+ * Request:
+ * POST /api/conversation/{conversation_id}/read
+ * {
+ *     "last_read_message_id": 120
+ * }
+ *
+ * Response:
+ * HTTP 204 No Content
+ *
+ * Read position never moves backward, so marking an older message is a no-op. It never moves past the last message of
+ * the conversation either, so that the messages sent afterward are counted as unread.
+ */
+
+use actix_web::{post, web, Responder, HttpResponse};
+use serde::Deserialize;
+
+use crate::{AppState, api::{ApiError, ConversationMember}};
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    last_read_message_id: i64,
+}
+
+#[post("/{conversation_id}/read")]
+async fn handler(member: ConversationMember, request: web::Json<Request>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    // Clamp to the last message of the conversation: an id beyond it would hide the unread messages to come.
+    sqlx::query!("UPDATE group_members
+        SET last_read_message_id = MAX(
+            COALESCE(last_read_message_id, 0),
+            MIN(?, COALESCE((SELECT MAX(id) FROM messages WHERE conversation_id = ?), 0)))
+        WHERE username = ? AND conversation_id = ?;", request.last_read_message_id, member.conversation_id, member.username, member.conversation_id)
+        .execute(&app_state.database)
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,19 +1,202 @@
+use std::collections::HashSet;
+
 use actix_web::web;
-use sqlx::SqlitePool;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqlitePool, SqliteExecutor, SqliteConnection};
+
+use crate::{AppState, api::{ApiError, ConversationMember, message::Message}, websocket::{server::SendToConversation, protocol::Event}};
 
 mod get_joined_conversations;
 mod create_new_conversation;
 mod get_conversation;
 mod get_conversation_messages;
 mod send_conversation_message;
+mod mark_conversation_read;
+mod add_conversation_members;
+mod remove_conversation_member;
+mod leave_conversation;
+mod delete_conversation;
+mod change_member_role;
+mod transfer_conversation_ownership;
+mod update_conversation;
+mod update_conversation_avatar;
+mod delete_conversation_avatar;
+mod get_direct_conversation;
+mod role;
+
+pub use role::{MemberRole, Permission};
+
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationKind{
+    Group,
+    /// One-to-one conversation, unique for its two participants. Nobody owns it, so its details and members cannot
+    /// be changed.
+    Direct,
+}
 
-async fn is_user_joined_in_conversation(database: &SqlitePool, username: &str, conversation_id: i64) -> Result<bool, sqlx::Error>{
-    Ok(sqlx::query!("SELECT 1 AS x 
-        FROM group_members 
+/// Details of the conversation, as sent by the `conversation_updated` event.
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct ConversationInfo{
+    pub id: i64,
+    pub kind: ConversationKind,
+    /// Nicknames of the participants at the creation for the direct conversations, which clients should rather name
+    /// after the other participant.
+    pub name: String,
+    pub topic: Option<String>,
+    pub description: Option<String>,
+    /// Stored with the profile pictures, and served by `GET /api/user/profile_picture/{filename}`.
+    pub avatar_filename: Option<String>,
+}
+
+impl ConversationInfo{
+    pub async fn get(executor: impl SqliteExecutor<'_>, conversation_id: i64) -> Result<Option<ConversationInfo>, sqlx::Error>{
+        sqlx::query_as!(ConversationInfo, "SELECT id, kind AS \"kind: ConversationKind\", name, topic, description, avatar_filename
+            FROM conversations WHERE id = ?;", conversation_id)
+            .fetch_optional(executor)
+            .await
+    }
+}
+
+/// Get the role of the user in the conversation, `None` if the user is not a member.
+pub(crate) async fn get_member_role(executor: impl SqliteExecutor<'_>, username: &str, conversation_id: i64) -> Result<Option<MemberRole>, sqlx::Error>{
+    Ok(sqlx::query!("SELECT role AS \"role: MemberRole\"
+        FROM group_members
         WHERE username = ? AND conversation_id = ?;", username, conversation_id)
-        .fetch_optional(database)
+        .fetch_optional(executor)
+        .await?
+        .map(|record| record.role))
+}
+
+pub(crate) async fn get_joined_conversation_ids(database: &SqlitePool, username: &str) -> Result<HashSet<i64>, sqlx::Error>{
+    Ok(sqlx::query!("SELECT conversation_id FROM group_members WHERE username = ?;", username)
+        .fetch_all(database)
+        .await?
+        .into_iter()
+        .map(|record| record.conversation_id)
+        .collect())
+}
+
+/// Get the users sharing at least one conversation with the user, including the user.
+pub(crate) async fn get_conversation_peer_usernames(database: &SqlitePool, username: &str) -> Result<Vec<String>, sqlx::Error>{
+    let mut usernames: Vec<String> = sqlx::query!("SELECT DISTINCT peer.username
+        FROM group_members gm
+        INNER JOIN group_members peer USING (conversation_id)
+        WHERE gm.username = ?;", username)
+        .fetch_all(database)
+        .await?
+        .into_iter()
+        .map(|record| record.username)
+        .collect();
+
+    if !usernames.iter().any(|peer_username| peer_username == username){
+        usernames.push(username.to_owned());
+    }
+    Ok(usernames)
+}
+
+/// Conversation deleted with [`delete_conversation_if_empty`]. Its avatar should be removed once the transaction is
+/// committed.
+pub(crate) struct DeletedConversation{
+    pub avatar_filename: Option<String>,
+}
+
+/// Delete the conversation (and its messages) if it has no member anymore.
+///
+/// The caller should then send `Event::ConversationRemoved` to the conversation, so that `ChatServer` forgets it.
+pub(crate) async fn delete_conversation_if_empty(executor: impl SqliteExecutor<'_>, conversation_id: i64) -> Result<Option<DeletedConversation>, sqlx::Error>{
+    sqlx::query_as!(DeletedConversation, "DELETE FROM conversations
+        WHERE id = ? AND NOT EXISTS (SELECT 1 FROM group_members WHERE conversation_id = ?)
+        RETURNING avatar_filename;", conversation_id, conversation_id)
+        .fetch_optional(executor)
+        .await
+}
+
+/// Make a member the owner of the group conversation if it has none, e.g. after the owner left. The earliest admin is
+/// preferred, then the earliest member. Returns the system message announcing the new owner.
+pub(crate) async fn assign_successor_owner(tx: &mut SqliteConnection, conversation_id: i64) -> Result<Option<Message>, sqlx::Error>{
+    let owner_username = sqlx::query!("UPDATE group_members SET role = 'owner'
+        WHERE conversation_id = ?
+            AND username = (
+                SELECT username FROM group_members
+                WHERE conversation_id = ?
+                ORDER BY role = 'admin' DESC, joined_at, username
+                LIMIT 1)
+            AND NOT EXISTS (SELECT 1 FROM group_members WHERE conversation_id = ? AND role = 'owner')
+            AND EXISTS (SELECT 1 FROM conversations WHERE id = ? AND kind = 'group')
+        RETURNING username;", conversation_id, conversation_id, conversation_id, conversation_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|record| record.username);
+
+    match owner_username{
+        Some(owner_username) => {
+            let text = format!("{} is now the owner", get_nickname(&mut *tx, &owner_username).await?);
+            Ok(Some(Message::insert_system(&mut *tx, &owner_username, &text, conversation_id).await?))
+        },
+        None => Ok(None),
+    }
+}
+
+/// Set the avatar filename of the conversation, and write the system message recording it (unless nothing changed).
+/// Returns the previous filename, whose files the caller should remove, and the message.
+async fn replace_conversation_avatar(member: &ConversationMember, avatar_filename: Option<&str>, app_state: &AppState) -> Result<(Option<String>, Option<Message>), ApiError>{
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    let previous_filename = sqlx::query!("SELECT avatar_filename FROM conversations WHERE id = ?;", member.conversation_id)
+        .fetch_one(&mut *tx)
+        .await?
+        .avatar_filename;
+    if previous_filename.is_none() && avatar_filename.is_none(){
+        return Ok((None, None));
+    }
+
+    sqlx::query!("UPDATE conversations SET avatar_filename = ? WHERE id = ?;", avatar_filename, member.conversation_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let actor_nickname = get_nickname(&mut *tx, &member.username).await?;
+    let text = match avatar_filename{
+        Some(_) => format!("{actor_nickname} changed the conversation picture"),
+        None => format!("{actor_nickname} removed the conversation picture"),
+    };
+    let message = Message::insert_system(&mut *tx, &member.username, &text, member.conversation_id).await?;
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    Ok((previous_filename, Some(message)))
+}
+
+/// Send the system messages recording the changes of the conversation, then its updated details, to the subscribed
+/// sessions. Returns the updated details.
+async fn notify_conversation_updated(conversation_id: i64, messages: Vec<Message>, app_state: &AppState) -> Result<ConversationInfo, sqlx::Error>{
+    let conversation = ConversationInfo::get(&app_state.database, conversation_id)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+
+    for message in messages{
+        app_state.websocket_server.do_send(SendToConversation {
+            conversation_id,
+            event: Event::Message { conversation_id, message },
+        });
+    }
+    app_state.websocket_server.do_send(SendToConversation {
+        conversation_id,
+        event: Event::ConversationUpdated { conversation: conversation.clone() },
+    });
+
+    Ok(conversation)
+}
+
+/// Get the nickname of the user, e.g. to write a system message.
+async fn get_nickname(executor: impl SqliteExecutor<'_>, username: &str) -> Result<String, sqlx::Error>{
+    Ok(sqlx::query!("SELECT nickname FROM users WHERE username = ?;", username)
+        .fetch_one(executor)
         .await?
-        .is_some())
+        .nickname)
 }
 
 pub fn config(cfg: &mut web::ServiceConfig){
@@ -21,8 +204,19 @@ pub fn config(cfg: &mut web::ServiceConfig){
         web::scope("/conversation")
             .service(get_joined_conversations::handler)
             .service(create_new_conversation::handler)
+            .service(get_direct_conversation::handler)
             .service(get_conversation::handler)
             .service(get_conversation_messages::handler)
             .service(send_conversation_message::handler)
+            .service(mark_conversation_read::handler)
+            .service(add_conversation_members::handler)
+            .service(remove_conversation_member::handler)
+            .service(leave_conversation::handler)
+            .service(delete_conversation::handler)
+            .service(change_member_role::handler)
+            .service(transfer_conversation_ownership::handler)
+            .service(update_conversation::handler)
+            .service(update_conversation_avatar::handler)
+            .service(delete_conversation_avatar::handler)
     );
 }
\ No newline at end of file
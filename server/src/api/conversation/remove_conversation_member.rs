@@ -0,0 +1,66 @@
+/*
+ * Remove a member from the conversation.
+ *
+ * Request:
+ * DELETE /api/conversation/{conversation_id}/members/{username}
+ *
+ * Response:
+ * HTTP 204 No Content
+ *
+ * The owner can remove anyone, and the admins can remove the members without a role. A system message
+ * ("User 1 removed User 3") is written. Sessions of the removed user receive a
+ * `conversation_removed` event, and don't receive the events of the conversation anymore.
+ */
+
+use actix_web::{delete, web, Responder, HttpResponse};
+
+use crate::{
+    AppState,
+    api::{ApiError, ConversationMember, message::Message},
+    websocket::{server::{SendToConversation, SendToUsers}, protocol::Event},
+};
+
+use super::{get_nickname, MemberRole, Permission};
+
+#[delete("/{conversation_id}/members/{username}")]
+async fn handler(member: ConversationMember, path: web::Path<(i64, String)>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    let (_, username) = path.into_inner();
+
+    // VALIDATION: Members cannot remove themselves.
+    if username == member.username{
+        return Err(ApiError::InvalidRequest("You cannot remove yourself from the conversation.".to_owned()));
+    }
+
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    let removed_role = sqlx::query!("DELETE FROM group_members WHERE username = ? AND conversation_id = ?
+        RETURNING role AS \"role: MemberRole\";", username, member.conversation_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|record| record.role);
+    let Some(removed_role) = removed_role else{
+        return Err(ApiError::NotFound(format!("User {username} is not a member of this conversation.")));
+    };
+
+    // VALIDATION: Role of the member must be higher than the removed one's.
+    member.require(Permission::RemoveMember(removed_role))?;
+
+    let text = format!("{} removed {}", get_nickname(&mut *tx, &member.username).await?, get_nickname(&mut *tx, &username).await?);
+    let message = Message::insert_system(&mut *tx, &member.username, &text, member.conversation_id).await?;
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    // The removed user is still subscribed, so they also receive the system message.
+    app_state.websocket_server.do_send(SendToConversation {
+        conversation_id: member.conversation_id,
+        event: Event::Message { conversation_id: member.conversation_id, message },
+    });
+    app_state.websocket_server.do_send(SendToUsers {
+        usernames: vec![username],
+        event: Event::ConversationRemoved { conversation_id: member.conversation_id },
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
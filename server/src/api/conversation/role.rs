@@ -0,0 +1,64 @@
+//! Roles of the conversation members, and what each of them is allowed to do.
+
+use serde::{Deserialize, Serialize};
+
+/// Role of the member in the conversation, ordered from the least to the most privileged.
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MemberRole{
+    Member,
+    /// Appointed by the owner.
+    Admin,
+    /// Creator of the conversation, unless the ownership was transferred. Every conversation has exactly one.
+    Owner,
+}
+
+/// Action on the conversation which not every member is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission{
+    /// Rename the conversation, and change its topic, description and avatar.
+    Edit,
+    AddMembers,
+    /// Remove a member having the role.
+    RemoveMember(MemberRole),
+    /// Appoint or dismiss the admins, and transfer the ownership.
+    ChangeRoles,
+    DeleteConversation,
+}
+
+impl MemberRole{
+    /// Whether the member having the role is allowed to do the action.
+    ///
+    /// Owner is allowed to do everything. Admins are allowed to edit the conversation, to add members, and to remove
+    /// members having a lower role.
+    pub fn allows(self, permission: Permission) -> bool{
+        match permission{
+            Permission::Edit | Permission::AddMembers => self >= MemberRole::Admin,
+            Permission::RemoveMember(role) => self >= MemberRole::Admin && self > role,
+            Permission::ChangeRoles | Permission::DeleteConversation => self == MemberRole::Owner,
+        }
+    }
+}
+
+impl std::fmt::Display for MemberRole{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+        f.write_str(match self{
+            MemberRole::Member => "member",
+            MemberRole::Admin => "admin",
+            MemberRole::Owner => "owner",
+        })
+    }
+}
+
+impl std::fmt::Display for Permission{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+        match self{
+            Permission::Edit => f.write_str("edit the conversation"),
+            Permission::AddMembers => f.write_str("add members"),
+            Permission::RemoveMember(role) => write!(f, "remove a member with the {role} role"),
+            Permission::ChangeRoles => f.write_str("change the roles"),
+            Permission::DeleteConversation => f.write_str("delete the conversation"),
+        }
+    }
+}
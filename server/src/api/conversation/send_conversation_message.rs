@@ -1,28 +1,33 @@
-use actix_session::Session;
-use actix_web::{web, Responder, HttpResponse, Error, post};
+use actix_web::{web, Responder, HttpResponse, post};
+use serde::Deserialize;
 
-use crate::{AppState, api::{user::User, conversation::is_user_joined_in_conversation, map_internal_error}};
-use crate::api::message::Message;
+use crate::{AppState, api::{ApiError, ConversationMember}, websocket::{server::SendToConversation, protocol::Event}};
+use crate::api::message::{Message, MAX_CLIENT_ID_LENGTH};
 
-#[post("/{conversation_id}/message")]
-pub async fn handler(path: web::Path<i64>, text: String, app_state: web::Data<AppState>, session: Session) -> Result<impl Responder, Error>{
-    // VALIDATION: User must be logged in.
-    let username = match User::get_username_from_session(session){
-        Some(username) => username,
-        None => return Ok(HttpResponse::Unauthorized().finish())
-    };
+#[derive(Deserialize, Debug)]
+pub struct Query{
+    /// Client-generated message id. Retrying the request with the same id doesn't create a duplicate message.
+    client_id: Option<String>,
+}
 
-    // VALIDATION: Check if user joined to the given conversation.
-    let conversation_id = path.into_inner();
-    if !is_user_joined_in_conversation(&app_state.database, &username, conversation_id)
-        .await.map_err(map_internal_error)?{
-        return Ok(HttpResponse::Forbidden().body("You are not joined to this conversation."))
+#[post("/{conversation_id}/message")]
+pub async fn handler(member: ConversationMember, query: web::Query<Query>, text: String, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    // VALIDATION: Client id must not be too long.
+    if query.client_id.as_ref().is_some_and(|client_id| client_id.len() > MAX_CLIENT_ID_LENGTH){
+        return Err(ApiError::InvalidRequest(format!("Client id should be at most {MAX_CLIENT_ID_LENGTH} characters long.")));
     }
     
-    let message = sqlx::query_as!(Message, 
-            "INSERT INTO messages(sender_username, text, sent_at, conversation_id) VALUES (?, ?, DATETIME('NOW'), ?)
-            RETURNING id, sender_username, text, sent_at;", username, text, conversation_id)
-        .fetch_one(&app_state.database)
-        .await.unwrap();
+    // VALIDATION: Client id must not be used by a message of another conversation.
+    let (message, is_new) = Message::insert(&app_state.database, &member.username, &text, member.conversation_id, query.client_id.as_deref())
+        .await?
+        .ok_or_else(|| ApiError::Conflict("Client id is already used by a message of another conversation.".to_owned()))?;
+
+    // Broadcast to the subscribed sessions, unless it was already broadcast by a previous attempt.
+    if is_new{
+        app_state.websocket_server.do_send(SendToConversation {
+            conversation_id: member.conversation_id,
+            event: Event::Message { conversation_id: member.conversation_id, message: message.clone() },
+        });
+    }
     Ok(HttpResponse::Ok().json(message))
 }
\ No newline at end of file
@@ -0,0 +1,72 @@
+/*
+ * Transfer the ownership of the conversation to another member. The previous owner becomes an admin.
+ *
+ * Request:
+ * POST /api/conversation/{conversation_id}/transfer_ownership
+ * {
+ *     "username": "user3"
+ * }
+ *
+ * Response:
+ * HTTP 204 No Content
+ *
+ * A system message ("User 1 transferred the ownership to User 3") is written.
+ */
+
+use actix_web::{post, web, Responder, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    AppState,
+    api::{ApiError, ConversationMember, message::Message},
+    websocket::{server::SendToConversation, protocol::Event},
+};
+
+use super::{get_nickname, get_member_role, Permission};
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    username: String,
+}
+
+#[post("/{conversation_id}/transfer_ownership")]
+async fn handler(member: ConversationMember, request: web::Json<Request>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    member.require(Permission::ChangeRoles)?;
+
+    // VALIDATION: Ownership must be transferred to someone else.
+    if request.username == member.username{
+        return Err(ApiError::InvalidRequest("You already own the conversation.".to_owned()));
+    }
+
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    if get_member_role(&mut *tx, &request.username, member.conversation_id).await?.is_none(){
+        return Err(ApiError::NotFound(format!("User {} is not a member of this conversation.", request.username)));
+    }
+
+    // Demote first: a conversation has at most one owner. The ownership may have been transferred meanwhile.
+    let demoted = sqlx::query!("UPDATE group_members SET role = 'admin' WHERE username = ? AND conversation_id = ? AND role = 'owner';", member.username, member.conversation_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected() > 0;
+    if !demoted{
+        return Err(ApiError::Forbidden("You don't own the conversation anymore.".to_owned()));
+    }
+    sqlx::query!("UPDATE group_members SET role = 'owner' WHERE username = ? AND conversation_id = ?;", request.username, member.conversation_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let text = format!("{} transferred the ownership to {}", get_nickname(&mut *tx, &member.username).await?, get_nickname(&mut *tx, &request.username).await?);
+    let message = Message::insert_system(&mut *tx, &member.username, &text, member.conversation_id).await?;
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    app_state.websocket_server.do_send(SendToConversation {
+        conversation_id: member.conversation_id,
+        event: Event::Message { conversation_id: member.conversation_id, message },
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
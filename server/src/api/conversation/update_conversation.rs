@@ -0,0 +1,113 @@
+/*
+ * Rename the conversation, or change its topic or description. Only the owner and the admins can edit them.
+ *
+ * Request:
+ * PATCH /api/conversation/{conversation_id}
+ * {
+ *     "name": "Conversation 1", // optional
+ *     "topic": "Trip to Jeju", // optional, empty to clear
+ *     "description": "Plans of the trip." // optional, empty to clear
+ * }
+ *
+ * Response:
+ * HTTP 200 OK
+ * {
+ *     "id": 1,
+ *     "name": "Conversation 1",
+ *     "topic": "Trip to Jeju",
+ *     "description": "Plans of the trip.",
+ *     "avatar_filename": null
+ * }
+ *
+ * A system message ("User 1 renamed the conversation to Conversation 1") is written for each changed field, and the
+ * subscribed sessions receive a `conversation_updated` event.
+ */
+
+use actix_web::{patch, web, Responder, HttpResponse};
+use serde::Deserialize;
+
+use crate::{AppState, api::{ApiError, ConversationMember, message::Message}};
+
+use super::{get_nickname, notify_conversation_updated, ConversationInfo, Permission};
+
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_TOPIC_LENGTH: usize = 100;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    name: Option<String>,
+    topic: Option<String>,
+    description: Option<String>,
+}
+
+#[patch("/{conversation_id}")]
+async fn handler(member: ConversationMember, request: web::Json<Request>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    member.require(Permission::Edit)?;
+
+    // Whitespaces around are trimmed, and an empty topic or description is cleared.
+    let name = request.name.as_deref().map(str::trim);
+    let topic = request.topic.as_deref().map(str::trim).map(|topic| (!topic.is_empty()).then_some(topic));
+    let description = request.description.as_deref().map(str::trim).map(|description| (!description.is_empty()).then_some(description));
+
+    // VALIDATION: Name must not be blank, and the fields must not be too long.
+    if name.is_some_and(|name| !(1..=MAX_NAME_LENGTH).contains(&name.chars().count())){
+        return Err(ApiError::InvalidRequest(format!("Name should be between 1 to {MAX_NAME_LENGTH} characters long.")));
+    }
+    if topic.flatten().is_some_and(|topic| topic.chars().count() > MAX_TOPIC_LENGTH){
+        return Err(ApiError::InvalidRequest(format!("Topic should be at most {MAX_TOPIC_LENGTH} characters long.")));
+    }
+    if description.flatten().is_some_and(|description| description.chars().count() > MAX_DESCRIPTION_LENGTH){
+        return Err(ApiError::InvalidRequest(format!("Description should be at most {MAX_DESCRIPTION_LENGTH} characters long.")));
+    }
+
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    let current = ConversationInfo::get(&mut *tx, member.conversation_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Conversation does not exist.".to_owned()))?;
+    let actor_nickname = get_nickname(&mut *tx, &member.username).await?;
+
+    // Only the changed fields are updated and recorded.
+    let mut texts = Vec::new();
+    if let Some(name) = name.filter(|&name| name != current.name){
+        sqlx::query!("UPDATE conversations SET name = ? WHERE id = ?;", name, member.conversation_id)
+            .execute(&mut *tx)
+            .await?;
+        texts.push(format!("{actor_nickname} renamed the conversation to {name}"));
+    }
+    if let Some(topic) = topic.filter(|&topic| topic != current.topic.as_deref()){
+        sqlx::query!("UPDATE conversations SET topic = ? WHERE id = ?;", topic, member.conversation_id)
+            .execute(&mut *tx)
+            .await?;
+        texts.push(match topic{
+            Some(topic) => format!("{actor_nickname} changed the topic to {topic}"),
+            None => format!("{actor_nickname} cleared the topic"),
+        });
+    }
+    if let Some(description) = description.filter(|&description| description != current.description.as_deref()){
+        sqlx::query!("UPDATE conversations SET description = ? WHERE id = ?;", description, member.conversation_id)
+            .execute(&mut *tx)
+            .await?;
+        texts.push(match description{
+            Some(_) => format!("{actor_nickname} changed the description"),
+            None => format!("{actor_nickname} cleared the description"),
+        });
+    }
+
+    let mut messages = Vec::new();
+    for text in &texts{
+        messages.push(Message::insert_system(&mut *tx, &member.username, text, member.conversation_id).await?);
+    }
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    if messages.is_empty(){
+        return Ok(HttpResponse::Ok().json(current));
+    }
+
+    let conversation = notify_conversation_updated(member.conversation_id, messages, &app_state).await?;
+    Ok(HttpResponse::Ok().json(conversation))
+}
@@ -0,0 +1,55 @@
+/*
+ * Replace the avatar of the conversation. Only the owner and the admins can change it.
+ *
+ * Request:
+ * PUT /api/conversation/{conversation_id}/avatar
+ * Content-Type: multipart/form-data, with the image in the `avatar` field (same constraints as the profile pictures)
+ *
+ * Response:
+ * HTTP 200 OK
+ * {
+ *     "id": 1,
+ *     "name": "Conversation 1",
+ *     "topic": null,
+ *     "description": null,
+ *     "avatar_filename": "6f1c2a9e-....png"
+ * }
+ *
+ * The avatar is stored and served like the profile pictures (`GET /api/user/profile_picture/{filename}`), and the
+ * previous one is deleted. A system message ("User 1 changed the conversation picture") is written, and the
+ * subscribed sessions receive a `conversation_updated` event.
+ */
+
+use actix_multipart::form::{MultipartForm, tempfile::TempFile};
+use actix_web::{put, web, Responder, HttpResponse};
+
+use crate::{AppState, api::{ApiError, ConversationMember, user::{save_profile_picture, remove_profile_picture}}};
+
+use super::{replace_conversation_avatar, notify_conversation_updated, Permission};
+
+#[derive(MultipartForm, Debug)]
+struct Form{
+    avatar: TempFile,
+}
+
+#[put("/{conversation_id}/avatar")]
+async fn handler(member: ConversationMember, MultipartForm(form): MultipartForm<Form>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    member.require(Permission::Edit)?;
+
+    let img_filename = save_profile_picture(form.avatar, &app_state).await?;
+
+    let (previous_filename, message) = match replace_conversation_avatar(&member, Some(&img_filename), &app_state).await{
+        Ok(replaced) => replaced,
+        Err(err) => {
+            // Remove the saved avatar.
+            remove_profile_picture(&img_filename, &app_state);
+            return Err(err);
+        }
+    };
+    if let Some(previous_filename) = previous_filename{
+        remove_profile_picture(&previous_filename, &app_state);
+    }
+
+    let conversation = notify_conversation_updated(member.conversation_id, message.into_iter().collect(), &app_state).await?;
+    Ok(HttpResponse::Ok().json(conversation))
+}
@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use serde::Serialize;
+
+use crate::api::api_token::Scope;
+
+/// Error returned by the API handlers.
+///
+/// Responded as a JSON body with a machine-readable `code` and a human-readable `message`:
+///
+/// ```json
+/// { "code": "not_member", "message": "You are not joined to this conversation." }
+/// ```
+///
+/// Internal causes are logged, and never exposed to the client.
+#[derive(Debug)]
+pub enum ApiError{
+    /// Request is malformed, or violates a constraint (e.g. too short password).
+    InvalidRequest(String),
+    /// Request requires a logged in user.
+    Unauthorized,
+    /// Username and password don't match.
+    WrongCredentials,
+    /// Session user is not a member of the conversation.
+    NotMember,
+    /// API token of the request lacks the scope required by the request.
+    InsufficientScope(Scope),
+    /// Session user is not allowed to do the request, for another reason than the membership.
+    Forbidden(String),
+    /// Requested resource does not exist.
+    NotFound(String),
+    /// Username is already used by another user.
+    UsernameTaken,
+    /// Request conflicts with the existing data (unique constraint violation).
+    Conflict(String),
+    /// Something went wrong in the server. Holds the cause, which is only logged.
+    Internal(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a>{
+    code: &'a str,
+    message: &'a str,
+}
+
+impl ApiError{
+    /// Wrap an unexpected error, e.g. an I/O error.
+    pub fn internal(cause: impl Display) -> Self{
+        ApiError::Internal(cause.to_string())
+    }
+
+    pub fn code(&self) -> &'static str{
+        match self{
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::WrongCredentials => "wrong_credentials",
+            ApiError::NotMember => "not_member",
+            ApiError::InsufficientScope(_) => "insufficient_scope",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::UsernameTaken => "username_taken",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn message(&self) -> String{
+        match self{
+            ApiError::InvalidRequest(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message) => message.clone(),
+            ApiError::Unauthorized => "You must be logged in.".to_owned(),
+            ApiError::WrongCredentials => "Wrong username or password.".to_owned(),
+            ApiError::NotMember => "You are not joined to this conversation.".to_owned(),
+            ApiError::InsufficientScope(scope) => format!("API token requires the \"{scope}\" scope."),
+            ApiError::UsernameTaken => "Username already exists.".to_owned(),
+            ApiError::Internal(_) => "Internal server error.".to_owned(),
+        }
+    }
+}
+
+impl Display for ApiError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+        match self{
+            ApiError::Internal(cause) => write!(f, "{}: {cause}", self.code()),
+            _ => write!(f, "{}: {}", self.code(), self.message()),
+        }
+    }
+}
+
+impl ResponseError for ApiError{
+    fn status_code(&self) -> StatusCode{
+        match self{
+            ApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized | ApiError::WrongCredentials => StatusCode::UNAUTHORIZED,
+            ApiError::NotMember | ApiError::InsufficientScope(_) | ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::UsernameTaken | ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse{
+        if let ApiError::Internal(cause) = self{
+            log::error!("Internal server error: {cause}");
+        }
+
+        HttpResponse::build(self.status_code()).json(ErrorBody{ code: self.code(), message: &self.message() })
+    }
+}
+
+impl From<sqlx::Error> for ApiError{
+    fn from(err: sqlx::Error) -> Self{
+        match &err{
+            sqlx::Error::RowNotFound => ApiError::NotFound("Requested resource does not exist.".to_owned()),
+            sqlx::Error::Database(database_err) => match database_err.kind(){
+                sqlx::error::ErrorKind::UniqueViolation => ApiError::Conflict("Requested resource already exists.".to_owned()),
+                sqlx::error::ErrorKind::ForeignKeyViolation => ApiError::InvalidRequest("Request refers to an inexisting resource.".to_owned()),
+                sqlx::error::ErrorKind::NotNullViolation | sqlx::error::ErrorKind::CheckViolation => {
+                    ApiError::InvalidRequest("Request violates a constraint.".to_owned())
+                }
+                _ => ApiError::internal(err),
+            },
+            _ => ApiError::internal(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for ApiError{
+    fn from(err: std::io::Error) -> Self{
+        ApiError::internal(err)
+    }
+}
+
+impl From<actix_web::error::BlockingError> for ApiError{
+    fn from(err: actix_web::error::BlockingError) -> Self{
+        ApiError::internal(err)
+    }
+}
@@ -1,10 +1,90 @@
 use chrono::NaiveDateTime;
 use serde::{Serialize, Deserialize};
+use sqlx::{SqlitePool, SqliteExecutor};
 
-#[derive(sqlx::FromRow, Serialize, Deserialize, Debug)]
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
 pub struct Message{
     pub id: i64,
-    pub sender_username: String,
+    /// `None` if the sender deleted their account.
+    pub sender_username: Option<String>,
     pub text: String,
-    pub sent_at: NaiveDateTime
-}
\ No newline at end of file
+    pub sent_at: NaiveDateTime,
+    pub kind: MessageKind,
+}
+
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind{
+    /// Written by the sender.
+    Text,
+    /// Written by the server to record an event of the conversation (e.g. "Alice added Bob"), on behalf of the
+    /// member who caused it.
+    System,
+}
+
+/// Maximum length of the client-generated message id.
+pub const MAX_CLIENT_ID_LENGTH: usize = 64;
+
+impl Message{
+    /// Persist a new message into the given conversation and return the stored row.
+    /// 
+    /// `client_id` is the id generated by the client for the message. If the sender already sent a message with
+    /// the same id (i.e. the request is retried), nothing is inserted and the stored message is returned with `false`.
+    /// `None` is returned if that message belongs to another conversation.
+    pub async fn insert(database: &SqlitePool, sender_username: &str, text: &str, conversation_id: i64, client_id: Option<&str>) -> Result<Option<(Message, bool)>, sqlx::Error>{
+        let message = sqlx::query_as!(Message, 
+                "INSERT INTO messages(sender_username, text, sent_at, conversation_id, client_id) VALUES (?, ?, DATETIME('NOW'), ?, ?)
+                ON CONFLICT (sender_username, client_id) DO NOTHING
+                RETURNING id, sender_username, text, sent_at, kind AS \"kind: MessageKind\";", sender_username, text, conversation_id, client_id)
+            .fetch_optional(database)
+            .await?;
+        if let Some(message) = message{
+            return Ok(Some((message, true)));
+        }
+
+        // Conflict only happens when client_id is given.
+        let message = sqlx::query_as!(Message, 
+                "SELECT id, sender_username, text, sent_at, kind AS \"kind: MessageKind\"
+                FROM messages
+                WHERE sender_username = ? AND client_id = ? AND conversation_id = ?;", sender_username, client_id, conversation_id)
+            .fetch_optional(database)
+            .await?;
+        Ok(message.map(|message| (message, false)))
+    }
+
+    /// Persist a new system message into the given conversation and return the stored row.
+    pub async fn insert_system(executor: impl SqliteExecutor<'_>, sender_username: &str, text: &str, conversation_id: i64) -> Result<Message, sqlx::Error>{
+        sqlx::query_as!(Message,
+                "INSERT INTO messages(sender_username, text, sent_at, conversation_id, kind) VALUES (?, ?, DATETIME('NOW'), ?, 'system')
+                RETURNING id, sender_username, text, sent_at, kind AS \"kind: MessageKind\";", sender_username, text, conversation_id)
+            .fetch_one(executor)
+            .await
+    }
+
+    /// Get up to `limit` messages of the conversation sent after the message `after_id`, oldest first.
+    pub async fn get_after(database: &SqlitePool, conversation_id: i64, after_id: i64, limit: i64) -> Result<Vec<Message>, sqlx::Error>{
+        sqlx::query_as!(Message, 
+                "SELECT id, sender_username, text, sent_at, kind AS \"kind: MessageKind\"
+                FROM messages
+                WHERE conversation_id = ? AND id > ?
+                ORDER BY id ASC
+                LIMIT ?;", conversation_id, after_id, limit)
+            .fetch_all(database)
+            .await
+    }
+
+    /// Get up to `limit` messages of the conversation sent before the message `before_id`, oldest first.
+    pub async fn get_before(database: &SqlitePool, conversation_id: i64, before_id: i64, limit: i64) -> Result<Vec<Message>, sqlx::Error>{
+        let mut messages = sqlx::query_as!(Message, 
+                "SELECT id, sender_username, text, sent_at, kind AS \"kind: MessageKind\"
+                FROM messages
+                WHERE conversation_id = ? AND id < ?
+                ORDER BY id DESC
+                LIMIT ?;", conversation_id, before_id, limit)
+            .fetch_all(database)
+            .await?;
+        messages.reverse();
+        Ok(messages)
+    }
+}
@@ -1,15 +1,34 @@
+//! REST API, served under `/api`.
+//!
+//! Handlers writing several rows run their statements between `// TRANSACTION START.` and `// TRANSACTION END.`.
+//! An error returned in between (e.g. with `?`) drops the `sqlx::Transaction` before `commit`, which rolls it back.
+
+use actix_multipart::form::MultipartFormConfig;
 use actix_web::web;
 
 pub mod user;
-mod conversation;
-mod map_internal_error;
+pub(crate) mod conversation;
+mod error;
+mod auth;
 pub(crate) mod message;
+pub(crate) mod api_token;
 
-pub use map_internal_error::map_internal_error;
+pub use error::ApiError;
+pub use auth::{AuthenticatedUser, ConversationMember, Credential};
 
 pub fn config(cfg: &mut web::ServiceConfig){
+    // Malformed requests are responded with the same JSON error body as the handlers' errors.
+    fn invalid_request(err: impl std::fmt::Display) -> actix_web::Error{
+        ApiError::InvalidRequest(err.to_string()).into()
+    }
+
     cfg.service(
         web::scope("/api")
+            .app_data(web::JsonConfig::default().error_handler(|err, _| invalid_request(err)))
+            .app_data(web::QueryConfig::default().error_handler(|err, _| invalid_request(err)))
+            .app_data(web::PathConfig::default().error_handler(|err, _| invalid_request(err)))
+            .app_data(web::FormConfig::default().error_handler(|err, _| invalid_request(err)))
+            .app_data(MultipartFormConfig::default().error_handler(|err, _| invalid_request(err)))
             .configure(user::config)
             .configure(conversation::config)
     );
@@ -0,0 +1,103 @@
+//! Image pipeline of the uploaded profile pictures.
+//!
+//! Uploads are never stored as is: the format is sniffed from the bytes (the declared content type and extension
+//! are ignored), the image is decoded, rotated upright by its EXIF orientation, cropped to the centered square,
+//! then re-encoded to PNG at [`FULL_SIZE`] and every [`THUMBNAIL_SIZES`]. Re-encoding the pixels drops the EXIF
+//! and every other metadata (e.g. the GPS location of a photo).
+
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, imageops::FilterType, io::{Reader, Limits}};
+
+/// Side of the stored picture, in pixels. Smaller uploads are not upscaled.
+pub const FULL_SIZE: u32 = 512;
+
+/// Sides of the thumbnails, selectable by `?size=` of `GET /api/user/profile_picture/{filename}`.
+pub const THUMBNAIL_SIZES: [u32; 3] = [64, 128, 256];
+
+/// Extension of the stored pictures.
+pub const EXTENSION: &str = "png";
+
+/// Uploads larger than this (in pixels, per side) are rejected before decoding, to bound the memory.
+const MAX_DIMENSION: u32 = 8192;
+
+/// Encoded pictures, the full size one first, then the thumbnails in [`THUMBNAIL_SIZES`] order.
+pub struct ProcessedAvatar{
+    pub full: Vec<u8>,
+    pub thumbnails: Vec<(u32, Vec<u8>)>,
+}
+
+/// Why an upload cannot be used as a profile picture.
+#[derive(Debug)]
+pub enum AvatarError{
+    UnsupportedFormat,
+    InvalidImage(String),
+    Encoding(String),
+}
+
+impl std::fmt::Display for AvatarError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+        match self{
+            AvatarError::UnsupportedFormat => f.write_str("Only PNG, JPEG, GIF and WebP images are allowed."),
+            AvatarError::InvalidImage(err) => write!(f, "Invalid image: {err}"),
+            AvatarError::Encoding(err) => write!(f, "Failed to encode the image: {err}"),
+        }
+    }
+}
+
+/// Run the pipeline on the uploaded bytes. CPU heavy: call it in `web::block`.
+pub fn process(bytes: &[u8]) -> Result<ProcessedAvatar, AvatarError>{
+    let format = match image::guess_format(bytes){
+        Ok(format @ (ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP)) => format,
+        _ => return Err(AvatarError::UnsupportedFormat),
+    };
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+
+    let mut reader = Reader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|err| AvatarError::InvalidImage(err.to_string()))?;
+
+    let image = apply_orientation(image, read_orientation(bytes));
+
+    // Crop the centered square.
+    let side = image.width().min(image.height());
+    let image = image.crop_imm((image.width() - side) / 2, (image.height() - side) / 2, side, side);
+
+    let full = if side > FULL_SIZE{ image.resize_exact(FULL_SIZE, FULL_SIZE, FilterType::Lanczos3) } else{ image };
+    let thumbnails = THUMBNAIL_SIZES.iter()
+        .map(|&size| Ok((size, encode(&full.resize_exact(size, size, FilterType::Lanczos3))?)))
+        .collect::<Result<Vec<_>, AvatarError>>()?;
+
+    Ok(ProcessedAvatar{ full: encode(&full)?, thumbnails })
+}
+
+/// EXIF orientation (1 to 8) of the image, 1 (upright) if it has none.
+fn read_orientation(bytes: &[u8]) -> u32{
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|exif| exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY).and_then(|field| field.value.get_uint(0)))
+        .unwrap_or(1)
+}
+
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage{
+    match orientation{
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+fn encode(image: &DynamicImage) -> Result<Vec<u8>, AvatarError>{
+    let mut bytes = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).map_err(|err| AvatarError::Encoding(err.to_string()))?;
+    Ok(bytes)
+}
@@ -0,0 +1,66 @@
+/*
+ * Change the password of the session user.
+ *
+ * Request:
+ * PUT /api/user/password
+ * {
+ *     "current_password": "Password123",
+ *     "new_password": "NewPassword123"
+ * }
+ *
+ * Response:
+ * HTTP 204 No Content
+ *
+ * Every other login session of the user is invalidated, and their websocket connections are closed after a
+ * `session_revoked` event. The session of the request stays logged in. API tokens are not affected.
+ */
+
+use actix_session::Session;
+use actix_web::{put, web, Responder, HttpResponse};
+use serde::Deserialize;
+
+use crate::{AppState, api::{ApiError, AuthenticatedUser}, websocket::{server::SendToUsers, protocol::Event}};
+
+use super::{User, profile::verify_current_password};
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    current_password: String,
+    new_password: String,
+}
+
+#[put("/password")]
+async fn handler(user: AuthenticatedUser, request: web::Json<Request>, app_state: web::Data<AppState>, session: Session) -> Result<impl Responder, ApiError>{
+    user.require_session()?;
+
+    if let Err(err) = User::check_password_constraint(&request.new_password){
+        return Err(ApiError::InvalidRequest(err.to_string()));
+    }
+
+    // VALIDATION: Current password must match.
+    if !verify_current_password(&user.username, &request.current_password, &app_state).await?{
+        return Err(ApiError::Forbidden("Current password is wrong.".to_owned()));
+    }
+
+    // Hashing is slow on purpose: don't block the worker.
+    let password_config = app_state.config.password.clone();
+    let password = request.new_password.clone();
+    let encrypted_password = web::block(move || User::hash_password(&password, &password_config)).await?;
+
+    let session_epoch = sqlx::query!("UPDATE users SET encrypted_password = ?, session_epoch = session_epoch + 1 WHERE username = ?
+        RETURNING session_epoch;", encrypted_password, user.username)
+        .fetch_one(&app_state.database)
+        .await?
+        .session_epoch;
+
+    // Keep the current session logged in, with a new session id.
+    session.renew();
+    User::add_username_into_session(session, &user.username, session_epoch)?;
+
+    app_state.websocket_server.do_send(SendToUsers {
+        usernames: vec![user.username],
+        event: Event::SessionRevoked,
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,79 @@
+/*
+ * Create a personal access token for the session user, e.g. for a bot or a CLI tool.
+ *
+ * Request:
+ * POST /api/user/tokens
+ * {
+ *     "name": "deploy bot",
+ *     "scopes": ["read", "write"],
+ *     "expires_in_days": 30
+ * }
+ *
+ * Response:
+ * HTTP 201 Created
+ * {
+ *     "token": "chat_5f0c...",
+ *     "id": 3,
+ *     "name": "deploy bot",
+ *     "scopes": ["read", "write"],
+ *     "created_at": "2023-10-01T00:00:00",
+ *     "expires_at": "2023-10-31T00:00:00",
+ *     "last_used_at": null
+ * }
+ *
+ * The token is sent as `Authorization: Bearer {token}`, and is only shown in this response. A token never expires if
+ * `expires_in_days` is omitted. Tokens cannot create other tokens: this request requires the browser session.
+ */
+
+use actix_web::{post, web, Responder, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, api::{ApiError, AuthenticatedUser, api_token::{ApiToken, Scope}}};
+
+const MAX_NAME_LENGTH: usize = 64;
+const MAX_EXPIRES_IN_DAYS: i64 = 365;
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    name: String,
+    scopes: Vec<Scope>,
+    expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct Response{
+    token: String,
+    #[serde(flatten)]
+    api_token: ApiToken,
+}
+
+#[post("/tokens")]
+async fn handler(user: AuthenticatedUser, request: web::Json<Request>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    user.require_session()?;
+
+    // VALIDATION: Name must not be empty or too long.
+    let name = request.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH{
+        return Err(ApiError::InvalidRequest(format!("Token name should be 1 to {MAX_NAME_LENGTH} characters long.")));
+    }
+
+    // VALIDATION: Token must have at least one scope.
+    let mut scopes = request.scopes.clone();
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty(){
+        return Err(ApiError::InvalidRequest("Token should have at least one scope.".to_owned()));
+    }
+
+    // VALIDATION: Expiry must be in range.
+    let expires_at = match request.expires_in_days{
+        Some(days) if !(1..=MAX_EXPIRES_IN_DAYS).contains(&days) => {
+            return Err(ApiError::InvalidRequest(format!("Token should expire in 1 to {MAX_EXPIRES_IN_DAYS} days.")));
+        }
+        Some(days) => Some(chrono::Utc::now().naive_utc() + chrono::Duration::days(days)),
+        None => None,
+    };
+
+    let (api_token, token) = ApiToken::create(&app_state.database, &user.username, name, &scopes, expires_at).await?;
+    Ok(HttpResponse::Created().json(Response{ token, api_token }))
+}
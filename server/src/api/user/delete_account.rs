@@ -0,0 +1,107 @@
+/*
+ * Delete the account of the session user.
+ *
+ * Request:
+ * DELETE /api/user
+ * {
+ *     "password": "Password123"
+ * }
+ *
+ * Response:
+ * HTTP 204 No Content
+ *
+ * The user leaves all their conversations, and their messages stay in the conversations without a sender
+ * (`sender_username` becomes null). Conversations left without members are deleted with their messages, and the
+ * conversations owned by the user get a new owner. The direct conversations of the user lose their `dm_key`, so an
+ * account registered later with the same username doesn't join them. The profile picture and the API tokens are deleted, every login session is
+ * invalidated, and the websocket connections are closed after a `session_revoked` event.
+ */
+
+use actix_session::Session;
+use actix_web::{delete, web, Responder, HttpResponse};
+use serde::Deserialize;
+
+use crate::{
+    AppState,
+    api::{ApiError, AuthenticatedUser, conversation::{delete_conversation_if_empty, assign_successor_owner}},
+    websocket::{server::{SendToConversation, SendToUsers}, protocol::Event},
+};
+
+use super::{User, profile::{verify_current_password, remove_profile_picture}};
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    password: String,
+}
+
+#[delete("")]
+async fn handler(user: AuthenticatedUser, request: web::Json<Request>, app_state: web::Data<AppState>, session: Session) -> Result<impl Responder, ApiError>{
+    user.require_session()?;
+
+    // VALIDATION: Password must match.
+    if !verify_current_password(&user.username, &request.password, &app_state).await?{
+        return Err(ApiError::Forbidden("Password is wrong.".to_owned()));
+    }
+
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    let conversation_ids = sqlx::query!("SELECT conversation_id FROM group_members WHERE username = ?;", user.username)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    // Direct conversations are keyed by the usernames (`{username}:{username}`), which can be registered again.
+    sqlx::query!("UPDATE conversations SET dm_key = NULL
+        WHERE kind = 'direct' AND ? IN (substr(dm_key, 1, instr(dm_key, ':') - 1), substr(dm_key, instr(dm_key, ':') + 1));", user.username)
+        .execute(&mut *tx)
+        .await?;
+
+    // Memberships and API tokens are deleted by `ON DELETE CASCADE`, and messages are anonymized by
+    // `ON DELETE SET NULL`.
+    let profile_picture_filename = sqlx::query!("DELETE FROM users WHERE username = ? RETURNING profile_picture_filename;", user.username)
+        .fetch_one(&mut *tx)
+        .await?
+        .profile_picture_filename;
+
+    let mut deleted_conversations = Vec::new();
+    let mut messages = Vec::new();
+    for record in conversation_ids{
+        if let Some(deleted) = delete_conversation_if_empty(&mut *tx, record.conversation_id).await?{
+            deleted_conversations.push((record.conversation_id, deleted));
+        }
+        else if let Some(message) = assign_successor_owner(&mut tx, record.conversation_id).await?{
+            messages.push((record.conversation_id, message));
+        }
+    }
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    for (conversation_id, message) in messages{
+        app_state.websocket_server.do_send(SendToConversation {
+            conversation_id,
+            event: Event::Message { conversation_id, message },
+        });
+    }
+    for (conversation_id, deleted) in deleted_conversations{
+        if let Some(filename) = deleted.avatar_filename{
+            remove_profile_picture(&filename, &app_state);
+        }
+        app_state.websocket_server.do_send(SendToConversation {
+            conversation_id,
+            event: Event::ConversationRemoved { conversation_id },
+        });
+    }
+
+    if let Some(filename) = profile_picture_filename{
+        remove_profile_picture(&filename, &app_state);
+    }
+
+    User::expire_session(session);
+    app_state.websocket_server.do_send(SendToUsers {
+        usernames: vec![user.username],
+        event: Event::SessionRevoked,
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
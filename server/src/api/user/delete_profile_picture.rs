@@ -0,0 +1,33 @@
+/*
+ * Remove the profile picture of the session user, who gets the default one.
+ *
+ * Request:
+ * DELETE /api/user/profile_picture
+ *
+ * Response:
+ * HTTP 200 OK
+ * {
+ *     "username": "user1",
+ *     "nickname": "User 1",
+ *     "profile_picture_filename": null
+ * }
+ *
+ * The picture file is deleted. Sessions of the users sharing a conversation with the session user receive a
+ * `profile_updated` event.
+ */
+
+use actix_web::{delete, web, Responder, HttpResponse};
+
+use crate::{AppState, api::{ApiError, AuthenticatedUser}};
+
+use super::profile::{replace_profile_picture, remove_profile_picture, notify_profile_updated};
+
+#[delete("/profile_picture")]
+async fn handler(user: AuthenticatedUser, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    if let Some(previous_filename) = replace_profile_picture(&user.username, None, &app_state).await?{
+        remove_profile_picture(&previous_filename, &app_state);
+    }
+
+    let user = notify_profile_updated(&user.username, &app_state).await?;
+    Ok(HttpResponse::Ok().json(user))
+}
@@ -1,11 +1,11 @@
-use actix_web::{get, Responder, web, HttpResponse, Error};
+use actix_web::{get, Responder, web, HttpResponse};
 
-use crate::{AppState, api::{user::User, map_internal_error}};
+use crate::{AppState, api::{user::User, ApiError}};
 
 #[get("/all")]
-pub async fn handler(app_state: web::Data<AppState>) -> Result<impl Responder, Error>{
+pub async fn handler(app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
     let users = sqlx::query_as!(User, "SELECT username, nickname, profile_picture_filename FROM users")
         .fetch_all(&app_state.database)
-        .await.map_err(map_internal_error)?;
+        .await?;
     Ok(HttpResponse::Ok().json(users))
 }
\ No newline at end of file
@@ -0,0 +1,31 @@
+/*
+ * Get the personal access tokens of the session user, newest first. Secrets are never included.
+ *
+ * Request:
+ * GET /api/user/tokens
+ *
+ * Response:
+ * HTTP 200 OK
+ * [
+ *     {
+ *         "id": 3,
+ *         "name": "deploy bot",
+ *         "scopes": ["read", "write"],
+ *         "created_at": "2023-10-01T00:00:00",
+ *         "expires_at": "2023-10-31T00:00:00",
+ *         "last_used_at": "2023-10-02T12:00:00"
+ *     }
+ * ]
+ */
+
+use actix_web::{get, web, Responder, HttpResponse};
+
+use crate::{AppState, api::{ApiError, AuthenticatedUser, api_token::ApiToken}};
+
+#[get("/tokens")]
+async fn handler(user: AuthenticatedUser, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    user.require_session()?;
+
+    let api_tokens = ApiToken::get_all(&app_state.database, &user.username).await?;
+    Ok(HttpResponse::Ok().json(api_tokens))
+}
@@ -0,0 +1,42 @@
+/*
+ * Get the generated profile picture of a user without one: the first letter of the username on a background
+ * color derived from the username.
+ *
+ * Request:
+ * GET /api/user/default_profile_picture/{username}
+ *
+ * Response:
+ * HTTP 200 OK
+ * Content-Type: image/svg+xml
+ * Cache-Control: private, max-age=86400
+ * (SVG image)
+ *
+ * The picture only depends on the username, so the user doesn't need to exist.
+ */
+
+use actix_web::{web, Responder, HttpResponse, get, http::header::{CacheControl, CacheDirective, ContentType}};
+use sha2::{Sha256, Digest};
+
+use crate::api::{ApiError, AuthenticatedUser};
+
+use super::User;
+
+#[get("/default_profile_picture/{username}")]
+async fn handler(_: AuthenticatedUser, username: web::Path<String>) -> Result<impl Responder, ApiError>{
+    // VALIDATION: Username must be valid, which also makes it safe to embed into the SVG.
+    if User::check_username_constraint(&username).is_err(){
+        return Err(ApiError::NotFound("User does not exist.".to_owned()));
+    }
+
+    let hue = u16::from_be_bytes(Sha256::digest(username.as_bytes())[..2].try_into().unwrap()) % 360;
+    let initial = username.chars().next().unwrap_or('?').to_ascii_uppercase();
+    let svg = format!(r##"<svg xmlns="http://www.w3.org/2000/svg" width="128" height="128" viewBox="0 0 128 128">
+<rect width="128" height="128" fill="hsl({hue}, 55%, 45%)"/>
+<text x="64" y="64" dy=".35em" text-anchor="middle" font-family="sans-serif" font-size="64" fill="#ffffff">{initial}</text>
+</svg>"##);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType(mime::IMAGE_SVG))
+        .insert_header(CacheControl(vec![CacheDirective::Private, CacheDirective::MaxAge(24 * 60 * 60)]))
+        .body(svg))
+}
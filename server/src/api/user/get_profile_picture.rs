@@ -1,10 +1,70 @@
+/*
+ * Get a profile picture, by its filename (`profile_picture_filename` of the user). Conversation avatars
+ * (`avatar_filename` of the conversation) are stored and served the same way.
+ *
+ * Request:
+ * GET /api/user/profile_picture/{filename}?size={size}
+ *
+ * `size` selects a square thumbnail of 64, 128 or 256 pixels. Without it, the full picture (at most 512 pixels) is
+ * returned. Pictures uploaded before the thumbnails were introduced are always returned as is.
+ *
+ * Response:
+ * HTTP 200 OK
+ * Cache-Control: private, max-age=31536000, immutable
+ * (image)
+ *
+ * Filenames are UUIDs, and a new picture always gets a new filename, so the response never changes and is cached
+ * for a year. `ETag` and `Last-Modified` are also sent, to answer the conditional requests with 304 Not Modified.
+ * Users without a profile picture use `GET /api/user/default_profile_picture/{username}`.
+ */
+
+use std::path::Path;
+
 use actix_files::NamedFile;
-use actix_web::{web, Responder, get};
+use actix_web::{web, Responder, get, http::header::{CacheControl, CacheDirective}};
+use serde::Deserialize;
+
+use crate::{AppState, api::{ApiError, AuthenticatedUser}};
+
+use super::{avatar::THUMBNAIL_SIZES, profile::{is_profile_picture_filename, thumbnail_filename}};
+
+#[derive(Deserialize, Debug)]
+struct Query{
+    size: Option<u32>,
+}
 
 #[get("/profile_picture/{filename}")]
-async fn handler(path: web::Path<String>) -> impl Responder{
-    // Anyone can access to specific people's profile picture, for now.
-    // TODO: why not use PathBuf?
-    let filename = path.into_inner();
-    NamedFile::open_async(format!("resources/images/profiles/{}", filename)).await
-}
\ No newline at end of file
+async fn handler(_: AuthenticatedUser, filename: web::Path<String>, query: web::Query<Query>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    // VALIDATION: Filename must be a stored one, so it cannot escape the directory.
+    if !is_profile_picture_filename(&filename){
+        return Err(ApiError::NotFound("Profile picture does not exist.".to_owned()));
+    }
+
+    // VALIDATION: Size must be one of the generated thumbnails.
+    if query.size.is_some_and(|size| !THUMBNAIL_SIZES.contains(&size)){
+        return Err(ApiError::InvalidRequest(format!("Size should be one of {THUMBNAIL_SIZES:?}.")));
+    }
+
+    let directory = Path::new(&app_state.config.storage.profile_pictures_directory);
+    let path = match query.size{
+        Some(size) => Some(directory.join(thumbnail_filename(&filename, size))).filter(|path| path.exists()),
+        None => None,
+    }.unwrap_or_else(|| directory.join(filename.as_str()));
+
+    let file = NamedFile::open_async(path)
+        .await
+        .map_err(|err| match err.kind(){
+            std::io::ErrorKind::NotFound => ApiError::NotFound("Profile picture does not exist.".to_owned()),
+            _ => err.into(),
+        })?;
+
+    Ok(file
+        .use_etag(true)
+        .use_last_modified(true)
+        .customize()
+        .insert_header(CacheControl(vec![
+            CacheDirective::Private,
+            CacheDirective::MaxAge(365 * 24 * 60 * 60),
+            CacheDirective::Extension("immutable".to_owned(), None),
+        ])))
+}
@@ -1,10 +1,10 @@
 use actix_session::Session;
-use actix_web::{web, Responder, HttpResponse, post, Error};
+use actix_web::{web, Responder, HttpResponse, post};
 use serde::Deserialize;
 
-use crate::{AppState, api::map_internal_error};
+use crate::{AppState, api::ApiError};
 
-use super::User;
+use super::{User, PasswordVerification};
 
 #[derive(Deserialize, Debug)]
 struct Form{
@@ -13,18 +13,47 @@ struct Form{
 }
 
 #[post("/login")]
-async fn handler(form: web::Form<Form>, app_state: web::Data<AppState>, session: Session) -> Result<impl Responder, Error>{
-    let encrypted_password = User::encrypt_password(&form.password);
-    let user = sqlx::query_as!(User, "SELECT username, nickname, profile_picture_filename FROM users WHERE username=? AND encrypted_password=?", form.username, encrypted_password)
+async fn handler(form: web::Form<Form>, app_state: web::Data<AppState>, session: Session) -> Result<impl Responder, ApiError>{
+    let record = sqlx::query!("SELECT username, nickname, profile_picture_filename, encrypted_password, session_epoch FROM users WHERE username = ?", form.username)
         .fetch_optional(&app_state.database)
-        .await.map_err(map_internal_error)?;
+        .await?;
+
+    let user = match record{
+        Some(record) => {
+            // Hashing is slow on purpose: don't block the worker.
+            let password_config = app_state.config.password.clone();
+            let password = form.password.clone();
+            let encrypted_password = record.encrypted_password.clone();
+            let verification = web::block(move || User::verify_password(&password, &encrypted_password, &password_config)).await?;
+
+            match verification{
+                PasswordVerification::Invalid => None,
+                PasswordVerification::Valid => Some(record),
+                PasswordVerification::ValidNeedsRehash => {
+                    // Upgrade the legacy (or outdated) hash, now that the plain password is known.
+                    let password_config = app_state.config.password.clone();
+                    let password = form.password.clone();
+                    let encrypted_password = web::block(move || User::hash_password(&password, &password_config)).await?;
+                    sqlx::query!("UPDATE users SET encrypted_password = ? WHERE username = ?", encrypted_password, record.username)
+                        .execute(&app_state.database)
+                        .await?;
+                    Some(record)
+                }
+            }
+        }
+        None => None
+    }.map(|record| (User{
+        username: record.username,
+        nickname: record.nickname,
+        profile_picture_filename: record.profile_picture_filename,
+    }, record.session_epoch));
 
     match user{
-        Some(user) => {
+        Some((user, session_epoch)) => {
             // Generate session key for the user.
-            user.add_username_into_session(session);
-            Ok(HttpResponse::SeeOther().append_header(("Location", "https://localhost:5173/#/")).finish())
+            User::add_username_into_session(session, &user.username, session_epoch)?;
+            Ok(HttpResponse::SeeOther().append_header(("Location", app_state.config.client.page_url("/"))).finish())
         }
-        _ => Ok(HttpResponse::Unauthorized().body("Wrong username or password."))
+        _ => Err(ApiError::WrongCredentials)
     }
 }
\ No newline at end of file
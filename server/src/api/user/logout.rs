@@ -1,10 +1,10 @@
 use actix_session::Session;
-use actix_web::{Responder, HttpResponse, post};
+use actix_web::{web, Responder, HttpResponse, post};
 
-use crate::api::user::User;
+use crate::{AppState, api::user::User};
 
 #[post("/logout")]
-pub async fn handler(session: Session) -> impl Responder{
+pub async fn handler(app_state: web::Data<AppState>, session: Session) -> impl Responder{
     User::expire_session(session);
-    HttpResponse::SeeOther().append_header(("Location", "https://localhost:5173/#/login")).finish()
+    HttpResponse::SeeOther().append_header(("Location", app_state.config.client.page_url("/login"))).finish()
 }
\ No newline at end of file
@@ -1,13 +1,26 @@
+#[allow(clippy::module_inception)]
 mod user;
 use actix_web::web;
-pub use user::User;
+pub use user::{User, PasswordVerification};
+pub(crate) use profile::{save_profile_picture, remove_profile_picture};
 
+mod profile;
+mod avatar;
 mod login;
 mod logout;
 mod register;
 mod get_login_info;
 mod get_all_users;
 mod get_profile_picture;
+mod get_default_profile_picture;
+mod create_api_token;
+mod get_api_tokens;
+mod revoke_api_token;
+mod update_profile;
+mod update_profile_picture;
+mod delete_profile_picture;
+mod change_password;
+mod delete_account;
 
 pub fn config(cfg: &mut web::ServiceConfig){
     cfg.service(
@@ -18,5 +31,14 @@ pub fn config(cfg: &mut web::ServiceConfig){
             .service(get_login_info::handler)
             .service(get_all_users::handler)
             .service(get_profile_picture::handler)
+            .service(get_default_profile_picture::handler)
+            .service(create_api_token::handler)
+            .service(get_api_tokens::handler)
+            .service(revoke_api_token::handler)
+            .service(update_profile::handler)
+            .service(update_profile_picture::handler)
+            .service(delete_profile_picture::handler)
+            .service(change_password::handler)
+            .service(delete_account::handler)
     );
 }
\ No newline at end of file
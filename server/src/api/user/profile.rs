@@ -0,0 +1,137 @@
+//! Helpers shared by the handlers creating or editing the user accounts.
+
+use std::path::Path;
+
+use actix_multipart::form::tempfile::TempFile;
+use actix_web::web;
+
+use crate::{AppState, api::{ApiError, conversation::get_conversation_peer_usernames}, websocket::{server::SendToUsers, protocol::Event}};
+
+use super::{User, PasswordVerification, avatar::{self, AvatarError}};
+
+const MAX_PROFILE_PICTURE_SIZE: usize = 1024 * 1024 * 10; // 10MB
+
+/// Longest extension kept in the profile picture filenames.
+const MAX_EXTENSION_LENGTH: usize = 10;
+
+/// Whether the filename has the form of the stored profile pictures: a lowercase hyphenated UUID, followed by an
+/// optional extension of ASCII alphanumeric characters (e.g. `6f1c2a9e-0b7d-4c1e-9a43-2f7d1e0c5b8a.png`).
+pub fn is_profile_picture_filename(filename: &str) -> bool{
+    let (basename, extension) = match filename.split_once('.'){
+        Some((basename, extension)) => (basename, Some(extension)),
+        None => (filename, None),
+    };
+
+    // `Uuid::parse_str` also accepts the other formats (e.g. without hyphens), so compare with the formatted one.
+    let is_uuid = uuid::Uuid::parse_str(basename).is_ok_and(|uuid| uuid.hyphenated().to_string() == basename);
+    let is_extension = extension.is_none_or(|extension| {
+        (1..=MAX_EXTENSION_LENGTH).contains(&extension.len()) && extension.bytes().all(|byte| byte.is_ascii_alphanumeric())
+    });
+    is_uuid && is_extension
+}
+
+/// Validate the uploaded profile picture, run it through the image pipeline (see `avatar`), and persist the result
+/// and its thumbnails into the storage directory with a new UUID filename. Returns the filename.
+pub async fn save_profile_picture(image: TempFile, app_state: &AppState) -> Result<String, ApiError>{
+    // Check if image file size exceeds the limit.
+    if image.size > MAX_PROFILE_PICTURE_SIZE{
+        return Err(ApiError::InvalidRequest("Too large image file. Use less than 10 MB file.".to_owned()));
+    }
+
+    // Decoding and resizing are CPU heavy: don't block the worker.
+    let processed = web::block(move || {
+        let bytes = std::fs::read(image.file.path())?;
+        Ok::<_, std::io::Error>(avatar::process(&bytes))
+    }).await??.map_err(|err| match err{
+        AvatarError::Encoding(_) => ApiError::internal(err),
+        _ => ApiError::InvalidRequest(err.to_string()),
+    })?;
+
+    // Create new uuid for filename.
+    let img_filename = format!("{}.{}", uuid::Uuid::new_v4(), avatar::EXTENSION);
+    let directory = Path::new(&app_state.config.storage.profile_pictures_directory);
+
+    let files = std::iter::once((img_filename.clone(), processed.full))
+        .chain(processed.thumbnails.into_iter().map(|(size, bytes)| (thumbnail_filename(&img_filename, size), bytes)));
+    for (filename, bytes) in files{
+        if let Err(err) = std::fs::write(directory.join(&filename), bytes){
+            remove_profile_picture(&img_filename, app_state);
+            return Err(err.into());
+        }
+    }
+
+    Ok(img_filename)
+}
+
+/// Filename of the thumbnail of the profile picture, e.g. `{uuid}_64.png` for `{uuid}.png`.
+pub fn thumbnail_filename(filename: &str, size: u32) -> String{
+    match filename.split_once('.'){
+        Some((basename, extension)) => format!("{basename}_{size}.{extension}"),
+        None => format!("{filename}_{size}"),
+    }
+}
+
+/// Set the profile picture filename of the user, and return the previous one.
+pub async fn replace_profile_picture(username: &str, img_filename: Option<&str>, app_state: &AppState) -> Result<Option<String>, ApiError>{
+    // TRANSACTION START.
+    let mut tx = app_state.database.begin().await?;
+
+    let previous_filename = sqlx::query!("SELECT profile_picture_filename FROM users WHERE username = ?;", username)
+        .fetch_one(&mut *tx)
+        .await?
+        .profile_picture_filename;
+
+    sqlx::query!("UPDATE users SET profile_picture_filename = ? WHERE username = ?;", img_filename, username)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    // TRANSACTION END.
+
+    Ok(previous_filename)
+}
+
+/// Remove the profile picture file and its thumbnails. Failing to do so only leaves unused files, so the errors are
+/// only logged.
+pub fn remove_profile_picture(filename: &str, app_state: &AppState){
+    let directory = Path::new(&app_state.config.storage.profile_pictures_directory);
+    let filenames = std::iter::once(filename.to_owned())
+        .chain(avatar::THUMBNAIL_SIZES.iter().map(|&size| thumbnail_filename(filename, size)));
+
+    for filename in filenames{
+        match std::fs::remove_file(directory.join(&filename)){
+            // Pictures uploaded before the thumbnails were introduced don't have them.
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => log::warn!("Failed to remove the profile picture {filename}: {err}"),
+            _ => (),
+        }
+    }
+}
+
+/// Get the profile of the user, and let the sessions of every user sharing a conversation with them (including
+/// their other devices) know it was updated.
+pub async fn notify_profile_updated(username: &str, app_state: &AppState) -> Result<User, ApiError>{
+    let user = sqlx::query_as!(User, "SELECT username, nickname, profile_picture_filename FROM users WHERE username = ?;", username)
+        .fetch_one(&app_state.database)
+        .await?;
+
+    app_state.websocket_server.do_send(SendToUsers {
+        usernames: get_conversation_peer_usernames(&app_state.database, username).await?,
+        event: Event::ProfileUpdated { user: user.clone() },
+    });
+
+    Ok(user)
+}
+
+/// Check the password of the user, for the requests which need to confirm it.
+pub async fn verify_current_password(username: &str, password: &str, app_state: &AppState) -> Result<bool, ApiError>{
+    let encrypted_password = sqlx::query!("SELECT encrypted_password FROM users WHERE username = ?;", username)
+        .fetch_one(&app_state.database)
+        .await?
+        .encrypted_password;
+
+    // Hashing is slow on purpose: don't block the worker.
+    let password_config = app_state.config.password.clone();
+    let password = password.to_owned();
+    let verification = web::block(move || User::verify_password(&password, &encrypted_password, &password_config)).await?;
+    Ok(verification != PasswordVerification::Invalid)
+}
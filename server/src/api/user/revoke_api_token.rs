@@ -0,0 +1,32 @@
+/*
+ * Revoke a personal access token of the session user. Requests with the token are rejected afterwards, and the
+ * websocket connections opened with it are closed after a `token_revoked` event.
+ *
+ * Request:
+ * DELETE /api/user/tokens/{token_id}
+ *
+ * Response:
+ * HTTP 204 No Content
+ */
+
+use actix_web::{delete, web, Responder, HttpResponse};
+
+use crate::{AppState, api::{ApiError, AuthenticatedUser, api_token::ApiToken}, websocket::{server::SendToUsers, protocol::Event}};
+
+#[delete("/tokens/{token_id}")]
+async fn handler(user: AuthenticatedUser, token_id: web::Path<i64>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    user.require_session()?;
+
+    // VALIDATION: Token must belong to the session user.
+    let token_id = token_id.into_inner();
+    if !ApiToken::revoke(&app_state.database, &user.username, token_id).await?{
+        return Err(ApiError::NotFound("API token does not exist.".to_owned()));
+    }
+
+    app_state.websocket_server.do_send(SendToUsers {
+        usernames: vec![user.username],
+        event: Event::TokenRevoked { token_id },
+    });
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,46 @@
+/*
+ * Change the nickname of the session user.
+ *
+ * Request:
+ * PATCH /api/user/profile
+ * {
+ *     "nickname": "New nickname"
+ * }
+ *
+ * Response:
+ * HTTP 200 OK
+ * {
+ *     "username": "user1",
+ *     "nickname": "New nickname",
+ *     "profile_picture_filename": "6f1c2a9e-....png"
+ * }
+ *
+ * Sessions of the users sharing a conversation with the session user receive a `profile_updated` event.
+ */
+
+use actix_web::{patch, web, Responder, HttpResponse};
+use serde::Deserialize;
+
+use crate::{AppState, api::{ApiError, AuthenticatedUser}};
+
+use super::{User, profile::notify_profile_updated};
+
+#[derive(Deserialize, Debug)]
+struct Request{
+    nickname: String,
+}
+
+#[patch("/profile")]
+async fn handler(user: AuthenticatedUser, request: web::Json<Request>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    if let Err(err) = User::check_nickname_constraint(&request.nickname){
+        return Err(ApiError::InvalidRequest(err.to_string()));
+    }
+
+    let nickname = request.nickname.trim();
+    sqlx::query!("UPDATE users SET nickname = ? WHERE username = ?;", nickname, user.username)
+        .execute(&app_state.database)
+        .await?;
+
+    let user = notify_profile_updated(&user.username, &app_state).await?;
+    Ok(HttpResponse::Ok().json(user))
+}
@@ -0,0 +1,50 @@
+/*
+ * Replace the profile picture of the session user.
+ *
+ * Request:
+ * PUT /api/user/profile_picture
+ * Content-Type: multipart/form-data, with the image in the `profile` field (same constraints as the registration)
+ *
+ * Response:
+ * HTTP 200 OK
+ * {
+ *     "username": "user1",
+ *     "nickname": "User 1",
+ *     "profile_picture_filename": "6f1c2a9e-....png"
+ * }
+ *
+ * The previous picture file is deleted. Sessions of the users sharing a conversation with the session user receive
+ * a `profile_updated` event.
+ */
+
+use actix_multipart::form::{MultipartForm, tempfile::TempFile};
+use actix_web::{put, web, Responder, HttpResponse};
+
+use crate::{AppState, api::{ApiError, AuthenticatedUser}};
+
+use super::profile::{save_profile_picture, replace_profile_picture, remove_profile_picture, notify_profile_updated};
+
+#[derive(MultipartForm, Debug)]
+struct Form{
+    profile: TempFile,
+}
+
+#[put("/profile_picture")]
+async fn handler(user: AuthenticatedUser, MultipartForm(form): MultipartForm<Form>, app_state: web::Data<AppState>) -> Result<impl Responder, ApiError>{
+    let img_filename = save_profile_picture(form.profile, &app_state).await?;
+
+    let previous_filename = match replace_profile_picture(&user.username, Some(&img_filename), &app_state).await{
+        Ok(previous_filename) => previous_filename,
+        Err(err) => {
+            // Remove the saved profile picture.
+            remove_profile_picture(&img_filename, &app_state);
+            return Err(err);
+        }
+    };
+    if let Some(previous_filename) = previous_filename{
+        remove_profile_picture(&previous_filename, &app_state);
+    }
+
+    let user = notify_profile_updated(&user.username, &app_state).await?;
+    Ok(HttpResponse::Ok().json(user))
+}
@@ -1,12 +1,17 @@
 use std::fmt::Display;
 
 use actix_session::Session;
+use argon2::{Argon2, Algorithm, Version, Params, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::SaltString};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use sha2::{Sha512, Digest};
 
-const SESSION_USERNAME_KEY: &'static str = env!("SESSION_USERNAME_KEY");
+use crate::{config::PasswordConfig, api::ApiError};
 
-#[derive(Serialize, Deserialize, Debug)]
+const SESSION_USERNAME_KEY: &str = "chat_session_username";
+const SESSION_EPOCH_KEY: &str = "chat_session_epoch";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct User{
     pub username: String,
     pub nickname: String,
@@ -43,13 +48,75 @@ impl Display for PasswordConstraintError {
     }
 }
 
+pub enum NicknameConstraintError{
+    LengthError, // not between 1 to 30 characters, after trimming the whitespaces
+}
+
+impl Display for NicknameConstraintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match &self{
+            Self::LengthError => "Nickname should between 1 to 30 characters long.",
+        };
+        f.write_str(message)
+    }
+}
+
+/// Result of checking a password against the stored hash.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasswordVerification{
+    Invalid,
+    Valid,
+    /// Password is valid, but the hash is legacy or uses other cost parameters: it should be replaced by
+    /// `User::hash_password`.
+    ValidNeedsRehash,
+}
+
 impl User{
-    pub fn encrypt_password(plain_password: &str) -> String{
-        const PASSWORD_ENCRYPTION_SALT: &str = env!("PASSWORD_ENCRYPTION_SALT");
+    /// Hash password with Argon2id and a random salt, into PHC string format (`$argon2id$v=19$m=...`).
+    pub fn hash_password(plain_password: &str, config: &PasswordConfig) -> String{
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let salt = SaltString::encode_b64(&salt).unwrap();
+
+        let params = config.argon2_params().expect("Argon2 parameters are validated on load.");
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(plain_password.as_bytes(), &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    /// Check password against the stored hash, either in PHC string format or a legacy salted SHA-512 hex digest.
+    pub fn verify_password(plain_password: &str, encrypted_password: &str, config: &PasswordConfig) -> PasswordVerification{
+        if let Ok(hash) = PasswordHash::new(encrypted_password){
+            // Algorithm and cost parameters are read from the hash itself.
+            if Argon2::default().verify_password(plain_password.as_bytes(), &hash).is_err(){
+                return PasswordVerification::Invalid;
+            }
+
+            let is_current = hash.algorithm == Algorithm::Argon2id.ident()
+                && Params::try_from(&hash).is_ok_and(|hash_params| {
+                    (hash_params.m_cost(), hash_params.t_cost(), hash_params.p_cost()) == (config.argon2_memory_kib, config.argon2_iterations, config.argon2_parallelism)
+                });
+            if is_current{
+                PasswordVerification::Valid
+            }
+            else{
+                PasswordVerification::ValidNeedsRehash
+            }
+        }
+        else if config.legacy_salt.as_ref().is_some_and(|salt| Self::legacy_encrypt_password(plain_password, salt) == encrypted_password){
+            PasswordVerification::ValidNeedsRehash
+        }
+        else{
+            PasswordVerification::Invalid
+        }
+    }
 
+    /// Password hash used before Argon2id, with a global salt. Only used to verify (and upgrade) the old hashes.
+    fn legacy_encrypt_password(plain_password: &str, salt: &str) -> String{
         let mut hasher = Sha512::new();
         hasher.update(plain_password);
-        hasher.update(PASSWORD_ENCRYPTION_SALT);
+        hasher.update(salt);
 
         format!("{:x}", hasher.finalize())
     }
@@ -98,15 +165,43 @@ impl User{
         }
     }
 
-    pub fn add_username_into_session(&self, session: Session){
-        session.insert(SESSION_USERNAME_KEY, &self.username).unwrap()
+    pub fn check_nickname_constraint(nickname: &str) -> Result<(), NicknameConstraintError>{
+        // Nickname may contain any character, but must not be blank.
+        if !(1..=30).contains(&nickname.trim().chars().count()){
+            Err(NicknameConstraintError::LengthError)
+        }
+        else{
+            Ok(())
+        }
+    }
+
+    /// Initial `users.session_epoch` of a new account. It is random, so the sessions of a deleted account don't match
+    /// the account re-registered with the same username. It leaves room for the increments of the password changes.
+    pub fn initial_session_epoch() -> i64{
+        (rand::thread_rng().next_u64() >> 2) as i64
+    }
+
+    /// `session_epoch` is the `users.session_epoch` of the user at login. The session is valid only while it
+    /// doesn't change (i.e. until the password is changed).
+    pub fn add_username_into_session(session: Session, username: &str, session_epoch: i64) -> Result<(), ApiError>{
+        session.insert(SESSION_USERNAME_KEY, username).map_err(ApiError::internal)?;
+        session.insert(SESSION_EPOCH_KEY, session_epoch).map_err(ApiError::internal)
     }
 
-    pub fn get_username_from_session(session: Session) -> Option<String>{
-        session.get::<String>(SESSION_USERNAME_KEY).unwrap()
+    /// Get the username and the session epoch of the session. Sessions created before the epochs were introduced
+    /// have epoch 0.
+    ///
+    /// Prefer `AuthenticatedUser` extractor in the handlers, which also checks the epoch.
+    pub fn get_username_from_session(session: &Session) -> Result<Option<(String, i64)>, ApiError>{
+        let Some(username) = session.get::<String>(SESSION_USERNAME_KEY).map_err(ApiError::internal)? else{
+            return Ok(None);
+        };
+        let session_epoch = session.get::<i64>(SESSION_EPOCH_KEY).map_err(ApiError::internal)?.unwrap_or(0);
+        Ok(Some((username, session_epoch)))
     }
 
     pub fn expire_session(session: Session){
         session.remove(SESSION_USERNAME_KEY);
+        session.remove(SESSION_EPOCH_KEY);
     }
 }
\ No newline at end of file
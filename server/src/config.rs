@@ -0,0 +1,189 @@
+//! Runtime configuration of the server.
+//!
+//! Read at startup from a TOML file (`config.toml` in the working directory, or the path in the `CHAT_CONFIG`
+//! environment variable), then overridden by the `CHAT_{SECTION}__{KEY}` environment variables, e.g.
+//! `CHAT_SERVER__BIND_ADDRESS=0.0.0.0:8443` or `CHAT_WEBSOCKET__FAN_OUT=redis`. Every key has a default suitable
+//! for local development, so the file is optional. See `config.toml` for the description of each key.
+
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config{
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub database: DatabaseConfig,
+    pub redis: RedisConfig,
+    pub client: ClientConfig,
+    pub session: SessionConfig,
+    pub password: PasswordConfig,
+    pub storage: StorageConfig,
+    pub websocket: WebsocketConfig,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ServerConfig{
+    pub bind_address: String,
+    /// Number of worker threads, one per CPU core if not given.
+    pub workers: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct TlsConfig{
+    pub certificate_path: String,
+    /// PKCS 8 private key.
+    pub private_key_path: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct DatabaseConfig{
+    /// SQLite database file, created if not exists.
+    pub url: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct RedisConfig{
+    pub address: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ClientConfig{
+    /// Origin of the web client, allowed by CORS and used for the redirections.
+    pub base_url: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SessionConfig{
+    /// Key signing the session cookies, at least 64 bytes. Every server instance must share the same key,
+    /// otherwise a random key is generated and the sessions don't survive a restart.
+    pub secret_key: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PasswordConfig{
+    /// Argon2id cost parameters for the new password hashes.
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    /// Global salt of the legacy SHA-512 password hashes. They cannot be verified if not given.
+    pub legacy_salt: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct StorageConfig{
+    pub profile_pictures_directory: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct WebsocketConfig{
+    pub fan_out: FanOutBackend,
+}
+
+/// See `websocket::fan_out`.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FanOutBackend{
+    /// Single server instance.
+    #[default]
+    Local,
+    /// Several server instances sharing the Redis at `redis.address`.
+    Redis,
+}
+
+impl Default for ServerConfig{
+    fn default() -> Self{
+        ServerConfig{
+            bind_address: "localhost:8443".to_owned(),
+            workers: None,
+        }
+    }
+}
+
+impl Default for TlsConfig{
+    fn default() -> Self{
+        TlsConfig{
+            certificate_path: "../cert.pem".to_owned(),
+            private_key_path: "../key.pem".to_owned(),
+        }
+    }
+}
+
+impl Default for DatabaseConfig{
+    fn default() -> Self{
+        DatabaseConfig{ url: "database.db".to_owned() }
+    }
+}
+
+impl Default for RedisConfig{
+    fn default() -> Self{
+        RedisConfig{ address: "127.0.0.1:6379".to_owned() }
+    }
+}
+
+impl Default for ClientConfig{
+    fn default() -> Self{
+        ClientConfig{ base_url: "https://localhost:5173".to_owned() }
+    }
+}
+
+impl Default for PasswordConfig{
+    fn default() -> Self{
+        // OWASP recommendation.
+        PasswordConfig{
+            argon2_memory_kib: argon2::Params::DEFAULT_M_COST,
+            argon2_iterations: argon2::Params::DEFAULT_T_COST,
+            argon2_parallelism: argon2::Params::DEFAULT_P_COST,
+            legacy_salt: None,
+        }
+    }
+}
+
+impl Default for StorageConfig{
+    fn default() -> Self{
+        StorageConfig{ profile_pictures_directory: "resources/images/profiles".to_owned() }
+    }
+}
+
+impl Config{
+    /// Load the configuration from the file and the environment variables, and validate it.
+    pub fn load() -> Result<Config, String>{
+        let path = std::env::var("CHAT_CONFIG").unwrap_or_else(|_| "config.toml".to_owned());
+
+        let config: Config = ::config::Config::builder()
+            .add_source(::config::File::new(&path, ::config::FileFormat::Toml).required(false))
+            .add_source(::config::Environment::with_prefix("CHAT").prefix_separator("_").separator("__").try_parsing(true))
+            .build()
+            .and_then(::config::Config::try_deserialize)
+            .map_err(|err| format!("Invalid configuration: {err}"))?;
+
+        if config.session.secret_key.as_ref().is_some_and(|key| key.len() < 64){
+            return Err("session.secret_key should be at least 64 bytes long.".to_owned());
+        }
+        config.password.argon2_params()?;
+
+        Ok(config)
+    }
+}
+
+impl ClientConfig{
+    /// URL of the client page at the given hash route, e.g. `/login`.
+    pub fn page_url(&self, route: &str) -> String{
+        format!("{}/#{route}", self.base_url.trim_end_matches('/'))
+    }
+}
+
+impl PasswordConfig{
+    pub fn argon2_params(&self) -> Result<argon2::Params, String>{
+        argon2::Params::new(self.argon2_memory_kib, self.argon2_iterations, self.argon2_parallelism, None)
+            .map_err(|err| format!("Invalid Argon2 parameters: {err}"))
+    }
+}
@@ -10,15 +10,17 @@ use rustls::{Certificate, PrivateKey, ServerConfig};
 use rustls_pemfile::{certs, pkcs8_private_keys};
 use sqlx::{SqlitePool, Sqlite, migrate::MigrateDatabase};
 
-use crate::websocket::server;
+use crate::{config::{Config, FanOutBackend, TlsConfig}, websocket::{server, fan_out::{FanOut, LocalFanOut, RedisFanOut}}};
 
 mod websocket;
 mod api;
+mod config;
 
 #[derive(Debug)]
 pub struct AppState{
     pub database: SqlitePool,
     pub websocket_server: actix::Addr<server::ChatServer>,
+    pub config: Config,
 }
 
 #[actix_web::main]
@@ -26,34 +28,66 @@ async fn main() -> std::io::Result<()>{
     // Initialize logger.
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
 
+    let config = match Config::load(){
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+
+    // Create the database and bring its schema up to date.
+    let database = prepare_database(&config.database.url).await;
+    if std::env::args().any(|arg| arg == "--migrate-only"){
+        log::info!("Database is migrated, exiting without starting the server.");
+        return Ok(());
+    }
+
+    // Websocket events are shared with the other server instances through Redis, if configured.
+    let fan_out: Box<dyn FanOut> = match config.websocket.fan_out{
+        FanOutBackend::Local => Box::new(LocalFanOut),
+        FanOutBackend::Redis => Box::new(RedisFanOut::new(config.redis.address.as_str())),
+    };
+
+    // Configure HTTP2 TLS connection.
+    let tls_config = load_rustls_config(&config.tls);
+
+    // Private key for redis. Generated keys are not shared with the other instances nor survive a restart.
+    let redis_private_key = match &config.session.secret_key{
+        Some(secret_key) => actix_web::cookie::Key::from(secret_key.as_bytes()),
+        None => {
+            log::warn!("session.secret_key is not configured, sessions are signed by a random key.");
+            actix_web::cookie::Key::generate()
+        }
+    };
+
     // Configure global app state.
     let app_state = web::Data::new(AppState {
-        database: prepare_database().await,
-        websocket_server: server::ChatServer::new().start()
+        database,
+        websocket_server: server::ChatServer::new(fan_out).start(),
+        config,
     });
 
-    // Configure HTTP2 TLS connection.
-    let config = load_rustls_config();
-
-    // Private key for redis.
-    let redis_private_key = actix_web::cookie::Key::generate();
+    let bind_address = app_state.config.server.bind_address.clone();
+    let workers = app_state.config.server.workers;
 
-    log::info!("Starting HTTPS server at https://localhost:8443");
+    log::info!("Starting HTTPS server at https://{bind_address}");
     let server = HttpServer::new(move || {
+        let config = &app_state.config;
         App::new()
             .app_data(app_state.clone())
             .wrap(middleware::Logger::default()) // enable logger.
             .wrap(
                 Cors::default() // <- Construct CORS middleware builder
-                    .allowed_origin("https://localhost:5173")
-                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+                    .allowed_origin(config.client.base_url.trim_end_matches('/'))
+                    .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
                     .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
                     .allowed_header(http::header::CONTENT_TYPE)
                     .supports_credentials()
                     .max_age(3600))
             .wrap(
                 SessionMiddleware::builder(
-                    RedisActorSessionStore::new("127.0.0.1:6379"),
+                    RedisActorSessionStore::new(config.redis.address.as_str()),
                     redis_private_key.clone()
                 ).build())
             .configure(api::config)
@@ -61,32 +95,30 @@ async fn main() -> std::io::Result<()>{
             // .service(web::route("/ws").to(websocket::chat_route))
     });
 
-    // Use different port num and workers for build target.
-    {
-        if cfg!(debug_assertions){ // Debug mode.
-            server.bind_rustls_021("localhost:8443", config)?
-                .workers(1) 
-        }
-        else{ // Release mode.
-            server.bind_rustls_021("0.0.0.0:8443", config)?
-        }
+    let server = server.bind_rustls_021(bind_address, tls_config)?;
+    match workers{
+        Some(workers) => server.workers(workers),
+        None => server,
     }
     .run()
     .await
 }
 
-async fn prepare_database() -> SqlitePool{
-    const DATABASE_URL: &str = "database.db";
-
+async fn prepare_database(database_url: &str) -> SqlitePool{
     // If database file not exists, create it.
-    if !Sqlite::database_exists(DATABASE_URL).await.unwrap_or(false){
-        Sqlite::create_database(DATABASE_URL).await.unwrap();
+    if !Sqlite::database_exists(database_url).await.unwrap_or(false){
+        Sqlite::create_database(database_url).await.unwrap();
     }
 
-    SqlitePool::connect(DATABASE_URL).await.unwrap()
+    let database = SqlitePool::connect(database_url).await.unwrap();
+
+    // Apply the migrations in `migrations/` (embedded in the binary) which are not applied yet.
+    sqlx::migrate!().run(&database).await.unwrap_or_else(|err| panic!("Failed to migrate the database: {err}"));
+
+    database
 }
 
-fn load_rustls_config() -> rustls::ServerConfig {
+fn load_rustls_config(tls: &TlsConfig) -> rustls::ServerConfig {
     // See https://github.com/actix/examples/tree/master/https-tls/rustls
 
     // init server config builder with safe defaults
@@ -95,8 +127,8 @@ fn load_rustls_config() -> rustls::ServerConfig {
         .with_no_client_auth();
 
     // load TLS key/cert files
-    let cert_file = &mut BufReader::new(File::open("../cert.pem").unwrap());
-    let key_file = &mut BufReader::new(File::open("../key.pem").unwrap());
+    let cert_file = &mut BufReader::new(File::open(&tls.certificate_path).unwrap());
+    let key_file = &mut BufReader::new(File::open(&tls.private_key_path).unwrap());
 
     // convert files to key/cert objects
     let cert_chain = certs(cert_file)
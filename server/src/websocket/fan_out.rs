@@ -0,0 +1,181 @@
+//! Fan-out of `ChatServer` events to the other server instances.
+//!
+//! `ChatServer` delivers every event to its own sessions, then hands it to a [`FanOut`] backend, which delivers
+//! it to the `ChatServer` of the other instances (as a [`Deliver`] message). With a single instance,
+//! [`LocalFanOut`] does nothing. [`RedisFanOut`] publishes the events to Redis channels, so that users connected
+//! to different instances (e.g. behind a load balancer) see each other's messages.
+
+use std::time::Duration;
+
+use actix::prelude::*;
+use futures::{channel::mpsc, StreamExt};
+use rand::Rng;
+use redis_async::{client::{paired_connect, pubsub_connect}, resp::FromResp, resp_array};
+use serde::{Deserialize, Serialize};
+
+use crate::websocket::protocol::Event;
+
+/// Recipients of an event.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    /// Sessions subscribed to the conversation.
+    Conversation(i64),
+    /// All sessions of the user.
+    User(String),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Envelope {
+    pub topic: Topic,
+    /// Session which should not receive the event (i.e. its sender). Only meaningful in the origin instance.
+    #[serde(skip)]
+    pub skip_id: Option<usize>,
+    pub event: Event,
+}
+
+/// Event published by another instance.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Deliver(pub Envelope);
+
+pub trait FanOut: std::fmt::Debug {
+    /// Start the backend. Events published by the other instances are sent to `server`.
+    fn start(&mut self, server: Recipient<Deliver>);
+
+    /// Publish an event, which was already delivered to the sessions of this instance.
+    fn publish(&mut self, envelope: &Envelope);
+}
+
+/// Backend for a single server instance: there is nobody else to deliver to.
+#[derive(Debug)]
+pub struct LocalFanOut;
+
+impl FanOut for LocalFanOut {
+    fn start(&mut self, _: Recipient<Deliver>) {}
+
+    fn publish(&mut self, _: &Envelope) {}
+}
+
+/// Channels are named `chat:conversation:{id}` and `chat:user:{username}`.
+const CHANNEL_PATTERN: &str = "chat:*";
+
+/// How long to wait before reconnecting to Redis.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Envelope as published to Redis, tagged with the instance which published it.
+#[derive(Serialize, Deserialize)]
+struct RedisEnvelope {
+    origin: u64,
+    envelope: Envelope,
+}
+
+/// Backend publishing events to Redis, for several server instances sharing the same Redis.
+#[derive(Debug)]
+pub struct RedisFanOut {
+    address: String,
+    /// Random id of this instance, to ignore the events published by itself.
+    instance_id: u64,
+    /// `(channel, payload)`s to publish, in order.
+    publisher: Option<mpsc::UnboundedSender<(String, String)>>,
+}
+
+impl RedisFanOut {
+    pub fn new(address: impl Into<String>) -> Self {
+        RedisFanOut {
+            address: address.into(),
+            instance_id: rand::thread_rng().gen(),
+            publisher: None,
+        }
+    }
+
+    fn channel(topic: &Topic) -> String {
+        match topic {
+            Topic::Conversation(conversation_id) => format!("chat:conversation:{conversation_id}"),
+            Topic::User(username) => format!("chat:user:{username}"),
+        }
+    }
+
+    async fn run_publisher(address: String, mut messages: mpsc::UnboundedReceiver<(String, String)>) {
+        let connection = loop {
+            match paired_connect(address.as_str()).await {
+                Ok(connection) => break connection,
+                Err(err) => {
+                    log::error!("Failed to connect to Redis for publishing: {err}");
+                    actix::clock::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        };
+
+        // Commands are sent in the order of `send_and_forget` calls, so events keep their order.
+        // The connection reconnects by itself if it drops: events published meanwhile are lost (and logged).
+        while let Some((channel, payload)) = messages.next().await {
+            connection.send_and_forget(resp_array!["PUBLISH", channel, payload]);
+        }
+    }
+
+    async fn run_subscriber(address: String, instance_id: u64, server: Recipient<Deliver>) {
+        loop {
+            let mut messages = match pubsub_connect(address.as_str()).await {
+                Ok(connection) => match connection.psubscribe(CHANNEL_PATTERN).await {
+                    Ok(messages) => messages,
+                    Err(err) => {
+                        log::error!("Failed to subscribe to Redis channels: {err}");
+                        actix::clock::sleep(RECONNECT_DELAY).await;
+                        continue;
+                    }
+                },
+                Err(err) => {
+                    log::error!("Failed to connect to Redis for subscribing: {err}");
+                    actix::clock::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+
+            while let Some(message) = messages.next().await {
+                let payload = match message.and_then(String::from_resp) {
+                    Ok(payload) => payload,
+                    Err(err) => {
+                        log::error!("Lost Redis subscription: {err}");
+                        break;
+                    }
+                };
+
+                match serde_json::from_str::<RedisEnvelope>(&payload) {
+                    Ok(RedisEnvelope { origin, envelope }) if origin != instance_id => server.do_send(Deliver(envelope)),
+                    Ok(_) => {} // Already delivered by this instance.
+                    Err(err) => log::error!("Invalid event published to Redis: {err}"),
+                }
+            }
+
+            actix::clock::sleep(RECONNECT_DELAY).await;
+        }
+    }
+}
+
+impl FanOut for RedisFanOut {
+    fn start(&mut self, server: Recipient<Deliver>) {
+        let (sender, receiver) = mpsc::unbounded();
+        self.publisher = Some(sender);
+
+        actix::spawn(Self::run_publisher(self.address.clone(), receiver));
+        actix::spawn(Self::run_subscriber(self.address.clone(), self.instance_id, server));
+    }
+
+    fn publish(&mut self, envelope: &Envelope) {
+        #[derive(Serialize)]
+        struct RedisEnvelopeRef<'a> {
+            origin: u64,
+            envelope: &'a Envelope,
+        }
+
+        let Some(publisher) = &self.publisher else {
+            return;
+        };
+
+        let payload = serde_json::to_string(&RedisEnvelopeRef { origin: self.instance_id, envelope }).unwrap();
+        if publisher.unbounded_send((Self::channel(&envelope.topic), payload)).is_err() {
+            log::error!("Redis publisher stopped, event is not delivered to the other instances.");
+        }
+    }
+}
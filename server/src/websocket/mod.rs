@@ -1,11 +1,10 @@
-use std::time::Instant;
-
-use actix_session::Session;
-use actix_web::{web, HttpRequest, Responder, get, HttpResponse};
+use actix_web::{web, HttpRequest, Responder, get};
 use actix_web_actors::ws;
 
-use crate::{AppState, api::user::User};
+use crate::{AppState, api::{AuthenticatedUser, Credential, api_token::Scope}};
 
+pub mod fan_out;
+pub mod protocol;
 pub mod server;
 pub mod session;
 
@@ -15,26 +14,20 @@ async fn chat_route(
     req: HttpRequest,
     stream: web::Payload,
     app_state: web::Data<AppState>,
-    session: Session
+    user: AuthenticatedUser
 ) -> impl Responder {
-    // Check if user logged in.
-    match User::get_username_from_session(session){
-        Some(username) => {
-            ws::start(
-                session::WsChatSession {
-                    id: 0,
-                    hb: Instant::now(),
-                    conversation_id: 0,
-                    username: username,
-                    // server_address: app_state.get_ref().websocket_server.clone(),
-                    app_state: app_state.clone()
-                },
-                &req,
-                stream,
-            )
-        }
-        None => Ok(HttpResponse::Unauthorized().finish())
-    }
+    // Upgrade is a GET request, so API tokens only need the read scope to connect.
+    let can_send = user.has_scope(Scope::Write);
+    // Sessions opened with an API token are closed when it is revoked.
+    let token_id = match user.credential {
+        Credential::Session => None,
+        Credential::ApiToken { id, .. } => Some(id),
+    };
+    ws::start(
+        session::WsChatSession::new(user.username, can_send, token_id, app_state.clone()),
+        &req,
+        stream,
+    )
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
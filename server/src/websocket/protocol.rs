@@ -0,0 +1,219 @@
+//! JSON protocol spoken over the `/ws/` socket.
+//!
+//! Every frame is a JSON object sent as a websocket text frame, and carries the protocol version in `v`.
+//! Right after the connection is established the server sends a `hello` event with the version it speaks.
+//!
+//! # Client requests
+//!
+//! ```json
+//! { "v": 3, "request_id": "42", "type": "subscribe", "conversation_ids": [3, 5], "last_seen_message_ids": { "3": 120 } }
+//! { "v": 3, "request_id": "43", "type": "unsubscribe", "conversation_ids": [3] }
+//! { "v": 3, "request_id": "44", "type": "send", "conversation_id": 5, "text": "Hello!", "client_id": "6f1c2a9e-..." }
+//! ```
+//!
+//! `request_id` is optional and opaque to the server: it is echoed back in the response to the request, so
+//! the client can match them. Each request gets exactly one response: `error`, or `message_ack` for `send` and
+//! `ack` for the others.
+//!
+//! - `subscribe`: start receiving events of the conversations, in addition to the already subscribed ones.
+//!   The session user must be a member of all of them, otherwise none of them is subscribed.
+//!   `last_seen_message_ids` is optional, and maps conversation ids to the id of the last message the client
+//!   has seen, e.g. before the connection dropped. The messages sent after it are replayed as `message` events
+//!   before the `ack`, then the live messages follow without duplicates. If more than [`MAX_REPLAY_MESSAGES`]
+//!   messages were missed, nothing is replayed for the conversation and a `replay_truncated` event is sent
+//!   instead: the client should reload the history with `GET /api/conversation/{conversation_id}/messages`.
+//! - `unsubscribe`: stop receiving events of the conversations. Unsubscribing a conversation which is not
+//!   subscribed is not an error.
+//! - `send`: persist a new message into a subscribed conversation and broadcast it to the other subscribers.
+//!   `client_id` is optional, and identifies the message for the sender (at most
+//!   [`MAX_CLIENT_ID_LENGTH`](crate::api::message::MAX_CLIENT_ID_LENGTH) characters, e.g. a UUID). Retrying a
+//!   `send` with the same `client_id` doesn't store nor broadcast the message again, and is acknowledged with
+//!   the message stored by the first attempt. Reusing a `client_id` in another conversation is a `conflict` error.
+//!
+//! Sessions opened with an API token (`Authorization: Bearer {token}` on the upgrade request) need the `read`
+//! scope to connect, and the `write` scope to `send`. Revoking the token closes them (see `token_revoked`).
+//!
+//! # Server responses
+//!
+//! ```json
+//! { "v": 3, "type": "ack", "request_id": "42" }
+//! { "v": 3, "type": "message_ack", "request_id": "44", "client_id": "6f1c2a9e-...", "conversation_id": 5,
+//!   "message_id": 121, "sent_at": "2023-10-01T00:00:00" }
+//! { "v": 3, "type": "error", "request_id": "43", "code": "not_member", "message": "You are not joined to this conversation." }
+//! ```
+//!
+//! `request_id` is `null` when the request did not have one, or could not be parsed at all. See [`ErrorCode`]
+//! for the possible `code`s.
+//!
+//! # Server events
+//!
+//! ```json
+//! { "v": 3, "type": "event", "event": "hello", "version": 3 }
+//! { "v": 3, "type": "event", "event": "message", "conversation_id": 3,
+//!   "message": { "id": 1, "sender_username": "user1", "text": "Hello!", "sent_at": "2023-10-01T00:00:00", "kind": "text" } }
+//! { "v": 3, "type": "event", "event": "replay_truncated", "conversation_id": 3 }
+//! { "v": 3, "type": "event", "event": "conversation_updated",
+//!   "conversation": { "id": 3, "name": "Conversation 3", "topic": "Trip", "description": null, "avatar_filename": null } }
+//! ```
+//!
+//! `message` has the same shape as the messages returned by `GET /api/conversation/{conversation_id}/messages`
+//! (`sender_username` is `null` if the sender deleted their account).
+//! `replay_truncated` is explained in `subscribe`.
+//! `conversation_updated` follows the system messages recording a change of the name, topic, description or
+//! avatar, with the updated details.
+//!
+//! Some events are sent to every session of the user, regardless of the subscriptions:
+//!
+//! ```json
+//! { "v": 3, "type": "event", "event": "conversation_added", "conversation_id": 7 }
+//! { "v": 3, "type": "event", "event": "profile_updated",
+//!   "user": { "username": "user1", "nickname": "User 1", "profile_picture_filename": "6f1c2a9e-....png" } }
+//! { "v": 3, "type": "event", "event": "conversation_removed", "conversation_id": 7 }
+//! { "v": 3, "type": "event", "event": "session_revoked" }
+//! { "v": 3, "type": "event", "event": "token_revoked", "token_id": 3 }
+//! ```
+//!
+//! - `conversation_added`: the user became a member of a new conversation, which can now be subscribed.
+//! - `conversation_removed`: the user was removed from the conversation, left it, or the conversation was deleted.
+//!   Every session of the user is unsubscribed from it, so no more events of the conversation follow.
+//! - `profile_updated`: a user sharing a conversation with the user (or the user themself) changed their nickname or
+//!   profile picture. `user` has the same shape as `GET /api/user/login_info`.
+//! - `session_revoked`: the user changed their password or deleted their account. The server closes the connection
+//!   right after. Reconnecting fails unless the login session is still valid (i.e. on the device which changed the
+//!   password).
+//! - `token_revoked`: the API token the session was opened with was revoked. Only the sessions opened with the token
+//!   receive it, and the server closes their connection right after.
+//!
+//! New events may be added without changing the version, so clients must ignore the events they don't know.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{message::Message, user::User, conversation::ConversationInfo};
+
+/// Version of the protocol. Requests with another version are rejected with `unsupported_version`.
+///
+/// - 1: initial version.
+/// - 2: `subscribe` and `unsubscribe` take several `conversation_ids`, and subscriptions no longer replace each other.
+/// - 3: `send` takes an optional `client_id`, and is acknowledged with `message_ack`.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Maximum number of missed messages replayed per conversation on `subscribe`.
+pub const MAX_REPLAY_MESSAGES: i64 = 200;
+
+/// Frame sent by the client.
+#[derive(Deserialize, Debug)]
+pub struct ClientFrame {
+    pub v: u32,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub request: Request,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Subscribe {
+        conversation_ids: Vec<i64>,
+        #[serde(default)]
+        last_seen_message_ids: HashMap<i64, i64>,
+    },
+    Unsubscribe { conversation_ids: Vec<i64> },
+    Send {
+        conversation_id: i64,
+        text: String,
+        #[serde(default)]
+        client_id: Option<String>,
+    },
+}
+
+/// Frame sent by the server, either as a response to a request or as an event.
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Ack {
+        request_id: Option<String>,
+    },
+    /// Response to `send`, with the id and timestamp of the stored message.
+    MessageAck {
+        request_id: Option<String>,
+        client_id: Option<String>,
+        conversation_id: i64,
+        message_id: i64,
+        sent_at: NaiveDateTime,
+    },
+    Error {
+        request_id: Option<String>,
+        code: ErrorCode,
+        message: String,
+    },
+    Event(Event),
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Frame is not a valid JSON request.
+    InvalidRequest,
+    /// `v` of the request is not [`PROTOCOL_VERSION`].
+    UnsupportedVersion,
+    /// Session user is not a member of the conversation.
+    NotMember,
+    /// Request targets a conversation which is not subscribed by the session.
+    NotSubscribed,
+    /// Session is authenticated by an API token without the scope required by the request (`write` for `send`).
+    InsufficientScope,
+    /// `client_id` of `send` was already used by the session user for a message of another conversation.
+    Conflict,
+    /// Something went wrong in the server. The request may be retried.
+    InternalError,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Hello { version: u32 },
+    Message { conversation_id: i64, message: Message },
+    ReplayTruncated { conversation_id: i64 },
+    ConversationUpdated { conversation: ConversationInfo },
+    ConversationAdded { conversation_id: i64 },
+    ConversationRemoved { conversation_id: i64 },
+    ProfileUpdated { user: User },
+    SessionRevoked,
+    TokenRevoked { token_id: i64 },
+}
+
+#[derive(Serialize)]
+struct VersionedFrame<T> {
+    v: u32,
+    #[serde(flatten)]
+    message: T,
+}
+
+/// Borrowed [`ServerMessage::Event`], to serialize an event without moving it.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum EventMessage<'a> {
+    Event(&'a Event),
+}
+
+impl ServerMessage {
+    pub fn error(request_id: Option<String>, code: ErrorCode, message: impl Into<String>) -> Self {
+        ServerMessage::Error { request_id, code, message: message.into() }
+    }
+
+    /// Serialize into a text frame, tagged with the protocol version.
+    pub fn to_frame(&self) -> String {
+        serde_json::to_string(&VersionedFrame { v: PROTOCOL_VERSION, message: self }).unwrap()
+    }
+}
+
+impl Event {
+    /// Serialize into a text frame, same as `ServerMessage::Event(event).to_frame()`.
+    pub fn to_frame(&self) -> String {
+        serde_json::to_string(&VersionedFrame { v: PROTOCOL_VERSION, message: EventMessage::Event(self) }).unwrap()
+    }
+}
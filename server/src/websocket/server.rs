@@ -1,24 +1,42 @@
 //! `ChatServer` is an actor. It maintains list of connection client session.
 //! And manages available conversations. Peers send messages to other peers in same
-//! conversation through `ChatServer`.
+//! conversation through `ChatServer`. Sessions connected to the other server instances
+//! are reached through the [`FanOut`] backend.
 
 use std::collections::{HashMap, HashSet};
 
 use actix::prelude::*;
 use rand::{self, rngs::ThreadRng, Rng};
 
-/// Chat server sends this messages to session
+use crate::websocket::{protocol::Event, fan_out::{FanOut, Envelope, Topic, Deliver}};
+
+/// Chat server sends this messages to session, as an already serialized protocol frame.
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct Message(pub String);
+pub struct Message {
+    pub frame: String,
+    /// Conversation whose subscribers the frame is sent to, `None` for the events sent to the users. Session drops
+    /// the frame if it is not subscribed to the conversation (anymore).
+    pub conversation_id: Option<i64>,
+    /// `(conversation_id, message_id)` of the chat message carried by the frame, if any. Session uses it to
+    /// skip the messages it already replayed.
+    pub chat_message: Option<(i64, i64)>,
+    /// Whether the session should close the connection after sending the frame.
+    pub close: bool,
+    /// Conversation the session was unsubscribed from, because the user is not a member anymore.
+    pub unsubscribed: Option<i64>,
+}
 
-/// Message for chat server communications
+// Message for chat server communications
 
 /// New chat session is created
 #[derive(Message)]
 #[rtype(usize)]
 pub struct Connect {
     pub addr: Recipient<Message>,
+    pub username: String,
+    /// Id of the API token the session was opened with, `None` for a login session.
+    pub token_id: Option<i64>,
 }
 
 /// Session is disconnected
@@ -34,18 +52,59 @@ pub struct Disconnect {
 pub struct ClientMessage {
     /// Id of the client session
     pub id: usize,
-    /// Peer message
-    pub msg: String,
-    /// Conversation name
+    /// Event to broadcast
+    pub event: Event,
+    /// Conversation id
     pub conversation: i64,
 }
 
-/// Join conversation, if conversation does not exists create new one.
+/// Send event to the sessions subscribed to the conversation, e.g. a system message written by a REST handler.
+///
+/// [`Event::ConversationRemoved`] also unsubscribes all the sessions, and forgets the (deleted) conversation.
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct Join {
-    pub id: usize, // client ID
+pub struct SendToConversation {
     pub conversation_id: i64,
+    pub event: Event,
+}
+
+/// Send event to all sessions (devices) of the users, regardless of their subscriptions.
+///
+/// [`Event::ConversationRemoved`] also unsubscribes the sessions from the conversation. [`Event::TokenRevoked`] is
+/// only sent to the sessions opened with the token, and closes them like [`Event::SessionRevoked`].
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendToUsers {
+    pub usernames: Vec<String>,
+    pub event: Event,
+}
+
+/// Subscribe session to the conversations, in addition to the already subscribed ones.
+///
+/// Membership must be verified by the caller.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Subscribe {
+    pub id: usize, // client ID
+    pub conversation_ids: Vec<i64>,
+}
+
+/// Unsubscribe session from the conversations.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Unsubscribe {
+    pub id: usize, // client ID
+    pub conversation_ids: Vec<i64>,
+}
+
+/// Connected session.
+#[derive(Debug)]
+struct Session {
+    addr: Recipient<Message>,
+    username: String,
+    token_id: Option<i64>,
+    /// Subscribed conversations, mirrored by `ChatServer::conversations`.
+    subscriptions: HashSet<i64>,
 }
 
 /// `ChatServer` manages chat conversations and responsible for coordinating chat session.
@@ -53,38 +112,122 @@ pub struct Join {
 /// Implementation is very naïve.
 #[derive(Debug)]
 pub struct ChatServer {
-    sessions: HashMap<usize, Recipient<Message>>,
-    conversations: HashMap<i64, HashSet<usize>>,
+    sessions: HashMap<usize, Session>,
+    users: HashMap<String, HashSet<usize>>, // Connected sessions by username. Never holds an empty set.
+    conversations: HashMap<i64, HashSet<usize>>, // Subscribed sessions by conversation. Never holds an empty set.
+    fan_out: Box<dyn FanOut>,
     rng: ThreadRng,
 }
 
 impl ChatServer {
-    pub fn new() -> ChatServer {
-        // default conversation
-        let mut conversations = HashMap::new();
-        conversations.insert(0, HashSet::new());
-
+    pub fn new(fan_out: Box<dyn FanOut>) -> ChatServer {
         ChatServer {
             sessions: HashMap::new(),
-            conversations,
+            users: HashMap::new(),
+            conversations: HashMap::new(),
+            fan_out,
             rng: rand::thread_rng(),
         }
     }
 }
 
 impl ChatServer {
+    /// Deliver event to the sessions of this instance, then to the other instances.
+    fn dispatch(&mut self, envelope: Envelope) {
+        self.deliver(&envelope);
+        self.fan_out.publish(&envelope);
+    }
+
+    /// Deliver event to the sessions of this instance.
+    fn deliver(&mut self, envelope: &Envelope) {
+        match &envelope.topic {
+            Topic::Conversation(conversation_id) => self.send_message(*conversation_id, &envelope.event, envelope.skip_id),
+            Topic::User(username) => self.send_user_message(username, &envelope.event),
+        }
+    }
+
     /// Send message to all users in the conversation
-    fn send_message(&self, conversation_id: i64, message: &str, skip_id: usize) {
-        if let Some(sessions) = self.conversations.get(&conversation_id) {
-            for id in sessions {
-                if *id != skip_id {
-                    if let Some(addr) = self.sessions.get(id) {
-                        addr.do_send(Message(message.to_owned()));
+    fn send_message(&mut self, conversation_id: i64, event: &Event, skip_id: Option<usize>) {
+        let Some(sessions) = self.conversations.get(&conversation_id).cloned() else {
+            return;
+        };
+
+        // Serialize once for all the sessions.
+        let chat_message = match event {
+            Event::Message { conversation_id, message } => Some((*conversation_id, message.id)),
+            _ => None,
+        };
+        let frame = event.to_frame();
+        let unsubscribed = Self::unsubscribed_conversation(event);
+
+        for id in sessions {
+            if Some(id) != skip_id {
+                if let Some(session) = self.sessions.get(&id) {
+                    session.addr.do_send(Message { frame: frame.clone(), conversation_id: Some(conversation_id), chat_message, close: false, unsubscribed });
+                }
+            }
+        }
+
+        // Conversation is deleted: forget it.
+        if unsubscribed.is_some() {
+            if let Some(sessions) = self.conversations.remove(&conversation_id) {
+                for id in sessions {
+                    if let Some(session) = self.sessions.get_mut(&id) {
+                        session.subscriptions.remove(&conversation_id);
                     }
                 }
             }
         }
     }
+
+    /// Send message to all sessions of the user
+    fn send_user_message(&mut self, username: &str, event: &Event) {
+        let Some(sessions) = self.users.get(username).cloned() else {
+            return;
+        };
+
+        let frame = event.to_frame();
+        let close = matches!(event, Event::SessionRevoked | Event::TokenRevoked { .. });
+        let unsubscribed = Self::unsubscribed_conversation(event);
+
+        for id in sessions {
+            // Sessions opened otherwise than with the revoked token are not concerned.
+            if let Event::TokenRevoked { token_id } = event {
+                if self.sessions.get(&id).is_some_and(|session| session.token_id != Some(*token_id)) {
+                    continue;
+                }
+            }
+
+            if let Some(conversation_id) = unsubscribed {
+                if let Some(session) = self.sessions.get_mut(&id) {
+                    session.subscriptions.remove(&conversation_id);
+                }
+                self.remove_subscriber(conversation_id, id);
+            }
+
+            if let Some(session) = self.sessions.get(&id) {
+                session.addr.do_send(Message { frame: frame.clone(), conversation_id: None, chat_message: None, close, unsubscribed });
+            }
+        }
+    }
+
+    /// Conversation the recipients of the event are unsubscribed from.
+    fn unsubscribed_conversation(event: &Event) -> Option<i64> {
+        match event {
+            Event::ConversationRemoved { conversation_id } => Some(*conversation_id),
+            _ => None,
+        }
+    }
+
+    /// Remove session from the subscribers of the conversation, and forget the conversation if nobody is left.
+    fn remove_subscriber(&mut self, conversation_id: i64, id: usize) {
+        if let Some(sessions) = self.conversations.get_mut(&conversation_id) {
+            sessions.remove(&id);
+            if sessions.is_empty() {
+                self.conversations.remove(&conversation_id);
+            }
+        }
+    }
 }
 
 /// Make actor from `ChatServer`
@@ -92,6 +235,10 @@ impl Actor for ChatServer {
     /// We are going to use simple Context, we just need ability to communicate
     /// with other actors.
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.fan_out.start(ctx.address().recipient());
+    }
 }
 
 /// Handler for Connect message.
@@ -101,15 +248,17 @@ impl Handler<Connect> for ChatServer {
     type Result = usize;
 
     fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
-        // notify all users in same conversation
-        // self.send_message(0, "Someone joined", 0);
-
         // register session with random id
         let id = self.rng.gen::<usize>();
-        self.sessions.insert(id, msg.addr);
 
-        // auto join session to main conversation
-        self.conversations.entry(0).or_default().insert(id);
+        // Session does not receive any broadcast until it subscribes a conversation (after the membership check).
+        self.users.entry(msg.username.clone()).or_default().insert(id);
+        self.sessions.insert(id, Session {
+            addr: msg.addr,
+            username: msg.username,
+            token_id: msg.token_id,
+            subscriptions: HashSet::new(),
+        });
 
         // send id back
         id
@@ -121,22 +270,19 @@ impl Handler<Disconnect> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        let mut conversation_ids = vec![];
-
-        // remove address
-        if self.sessions.remove(&msg.id).is_some() {
-            // remove session from all conversations
-            for (conversation_id, sessions) in &mut self.conversations {
-                if sessions.remove(&msg.id) {
-                    conversation_ids.push(*conversation_id);
+        // remove address, and the session from all its conversations
+        if let Some(session) = self.sessions.remove(&msg.id) {
+            for conversation_id in session.subscriptions {
+                self.remove_subscriber(conversation_id, msg.id);
+            }
+
+            if let Some(sessions) = self.users.get_mut(&session.username) {
+                sessions.remove(&msg.id);
+                if sessions.is_empty() {
+                    self.users.remove(&session.username);
                 }
             }
         }
-
-        // send message to other users
-        // for conversation_id in conversation_ids {
-        //     self.send_message(conversation_id, "Someone disconnected", 0);
-        // }
     }
 }
 
@@ -145,32 +291,83 @@ impl Handler<ClientMessage> for ChatServer {
     type Result = ();
 
     fn handle(&mut self, msg: ClientMessage, _: &mut Context<Self>) {
-        self.send_message(msg.conversation, msg.msg.as_str(), msg.id);
+        self.dispatch(Envelope {
+            topic: Topic::Conversation(msg.conversation),
+            skip_id: Some(msg.id),
+            event: msg.event,
+        });
     }
 }
 
-/// Join conversation, send disconnect message to old conversation
-/// send join message to new conversation
-impl Handler<Join> for ChatServer {
+/// Handler for SendToConversation message.
+impl Handler<SendToConversation> for ChatServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Join, _: &mut Context<Self>) {
-        let Join { id, conversation_id } = msg;
-        let mut conversations = Vec::new();
+    fn handle(&mut self, msg: SendToConversation, _: &mut Context<Self>) {
+        self.dispatch(Envelope {
+            topic: Topic::Conversation(msg.conversation_id),
+            skip_id: None,
+            event: msg.event,
+        });
+    }
+}
 
-        // remove session from all conversations
-        for (n, sessions) in &mut self.conversations {
-            if sessions.remove(&id) {
-                conversations.push(n.to_owned());
-            }
+/// Handler for SendToUsers message.
+impl Handler<SendToUsers> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendToUsers, _: &mut Context<Self>) {
+        for username in msg.usernames {
+            self.dispatch(Envelope {
+                topic: Topic::User(username),
+                skip_id: None,
+                event: msg.event.clone(),
+            });
+        }
+    }
+}
+
+/// Handler for Deliver message, from the other instances.
+impl Handler<Deliver> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Deliver, _: &mut Context<Self>) {
+        self.deliver(&msg.0);
+    }
+}
+
+/// Handler for Subscribe message.
+impl Handler<Subscribe> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Subscribe, _: &mut Context<Self>) {
+        let Subscribe { id, conversation_ids } = msg;
+        let Some(session) = self.sessions.get_mut(&id) else {
+            return;
+        };
+
+        for conversation_id in conversation_ids {
+            session.subscriptions.insert(conversation_id);
+            self.conversations.entry(conversation_id).or_default().insert(id);
         }
+    }
+}
+
+/// Handler for Unsubscribe message.
+impl Handler<Unsubscribe> for ChatServer {
+    type Result = ();
 
-        // send message to other users
-        // for conversation in conversations {
-        //     self.send_message(conversation, "Someone disconnected", 0);
-        // }
+    fn handle(&mut self, msg: Unsubscribe, _: &mut Context<Self>) {
+        let Unsubscribe { id, conversation_ids } = msg;
+        let Some(session) = self.sessions.get_mut(&id) else {
+            return;
+        };
 
-        self.conversations.entry(conversation_id).or_default().insert(id);
-        // self.send_message(conversation_id, "Someone connected", id);
+        for conversation_id in &conversation_ids {
+            session.subscriptions.remove(conversation_id);
+        }
+        for conversation_id in conversation_ids {
+            self.remove_subscriber(conversation_id, id);
+        }
     }
 }
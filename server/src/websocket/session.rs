@@ -1,11 +1,14 @@
-use std::time::{Duration, Instant};
+use std::{time::{Duration, Instant}, collections::{HashMap, HashSet}};
 
 use actix::prelude::*;
 use actix_web::web;
 use actix_web_actors::ws;
-use serde::Serialize;
 
-use crate::{websocket::server, AppState};
+use crate::{
+    websocket::{server, protocol::{ClientFrame, Request, ServerMessage, ErrorCode, Event, PROTOCOL_VERSION, MAX_REPLAY_MESSAGES}},
+    AppState,
+    api::{message::{Message, MAX_CLIENT_ID_LENGTH}, conversation::get_joined_conversation_ids}
+};
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
@@ -17,22 +20,31 @@ const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 pub struct WsChatSession {
     pub id: usize, // Unique session id
     pub hb: Instant, // Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT), otherwise we drop connection.
-    pub conversation_id: i64, // Joined conversation
+    pub subscriptions: HashSet<i64>, // Subscribed conversations, only added after the membership is verified
+    pub replayed_until: HashMap<i64, i64>, // Id of the last replayed message by conversation
     pub username: String, // Peer username
+    pub can_send: bool, // False if logged in by an API token without the write scope
+    pub token_id: Option<i64>, // API token the session is opened with, None if logged in by the session cookie
 
     /// Websocket chat server
     // pub server_address: Addr<server::ChatServer>,
     pub app_state: web::Data<AppState>
 }
 
-#[derive(Serialize)]
-enum WebsocketResponse{
-    JoinStatus { success: bool },
-    // Message { message: crate::api::message::Message },
-    InvalidRequest,
-}
-
 impl WsChatSession {
+    pub fn new(username: String, can_send: bool, token_id: Option<i64>, app_state: web::Data<AppState>) -> Self {
+        WsChatSession {
+            id: 0,
+            hb: Instant::now(),
+            subscriptions: HashSet::new(),
+            replayed_until: HashMap::new(),
+            username,
+            can_send,
+            token_id,
+            app_state
+        }
+    }
+
     /// helper method that sends ping to client every 5 seconds (HEARTBEAT_INTERVAL).
     ///
     /// also this method checks heartbeats from client
@@ -58,6 +70,142 @@ impl WsChatSession {
     }
 }
 
+impl WsChatSession {
+    fn reply(ctx: &mut ws::WebsocketContext<Self>, message: ServerMessage) {
+        ctx.text(message.to_frame());
+    }
+
+    fn subscribe(&mut self, request_id: Option<String>, conversation_ids: Vec<i64>, last_seen_message_ids: HashMap<i64, i64>, ctx: &mut ws::WebsocketContext<Self>) {
+        let database = self.app_state.database.clone();
+        let websocket_server = self.app_state.websocket_server.clone();
+        let username = self.username.clone();
+        let id = self.id;
+
+        async move {
+            // Check if user joined to all the given conversations.
+            let joined_conversation_ids = get_joined_conversation_ids(&database, &username).await?;
+            if !conversation_ids.iter().all(|conversation_id| joined_conversation_ids.contains(conversation_id)){
+                return Ok(None);
+            }
+
+            // Subscribe before looking up the missed messages: every message not found here is broadcasted to
+            // this session afterwards.
+            websocket_server.do_send(server::Subscribe { id, conversation_ids: conversation_ids.clone() });
+
+            let mut replays = Vec::new();
+            for conversation_id in &conversation_ids{
+                if let Some(&last_seen_message_id) = last_seen_message_ids.get(conversation_id){
+                    // Fetch one more than the limit to know if the replay should be truncated.
+                    let messages = Message::get_after(&database, *conversation_id, last_seen_message_id, MAX_REPLAY_MESSAGES + 1).await?;
+                    replays.push((*conversation_id, messages));
+                }
+            }
+
+            Ok::<_, sqlx::Error>(Some((conversation_ids, replays)))
+        }
+            .into_actor(self)
+            .map(move |result, act, ctx| {
+                let response = match result{
+                    Ok(Some((conversation_ids, replays))) => {
+                        act.subscriptions.extend(conversation_ids);
+
+                        for (conversation_id, messages) in replays{
+                            if messages.len() as i64 > MAX_REPLAY_MESSAGES{
+                                Self::reply(ctx, ServerMessage::Event(Event::ReplayTruncated { conversation_id }));
+                                continue;
+                            }
+
+                            if let Some(last_message) = messages.last(){
+                                act.replayed_until.insert(conversation_id, last_message.id);
+                            }
+                            for message in messages{
+                                Self::reply(ctx, ServerMessage::Event(Event::Message { conversation_id, message }));
+                            }
+                        }
+
+                        ServerMessage::Ack { request_id }
+                    }
+                    Ok(None) => ServerMessage::error(request_id, ErrorCode::NotMember, "You are not joined to this conversation."),
+                    Err(err) => {
+                        log::error!("Failed to subscribe conversations: {err}");
+                        ServerMessage::error(request_id, ErrorCode::InternalError, "Internal server error.")
+                    }
+                };
+                Self::reply(ctx, response);
+            })
+            .wait(ctx);
+    }
+
+    fn unsubscribe(&mut self, request_id: Option<String>, conversation_ids: Vec<i64>, ctx: &mut ws::WebsocketContext<Self>) {
+        for conversation_id in &conversation_ids{
+            self.subscriptions.remove(conversation_id);
+            self.replayed_until.remove(conversation_id);
+        }
+        self.app_state.websocket_server.do_send(server::Unsubscribe { id: self.id, conversation_ids });
+        Self::reply(ctx, ServerMessage::Ack { request_id });
+    }
+
+    fn send_message(&mut self, request_id: Option<String>, conversation_id: i64, text: String, client_id: Option<String>, ctx: &mut ws::WebsocketContext<Self>) {
+        // VALIDATION: Session must be allowed to send messages.
+        if !self.can_send{
+            Self::reply(ctx, ServerMessage::error(request_id, ErrorCode::InsufficientScope, "API token requires the \"write\" scope."));
+            return;
+        }
+
+        // VALIDATION: Session must be subscribed to the conversation (which implies the membership).
+        if !self.subscriptions.contains(&conversation_id){
+            Self::reply(ctx, ServerMessage::error(request_id, ErrorCode::NotSubscribed, "You are not subscribed to this conversation."));
+            return;
+        }
+
+        // VALIDATION: Client id must not be too long.
+        if client_id.as_ref().is_some_and(|client_id| client_id.len() > MAX_CLIENT_ID_LENGTH){
+            Self::reply(ctx, ServerMessage::error(request_id, ErrorCode::InvalidRequest,
+                format!("Client id should be at most {MAX_CLIENT_ID_LENGTH} characters long.")));
+            return;
+        }
+
+        // Persist the message before fan-out, so peers receive the stored row (with its id and sent_at).
+        // `wait` keeps messages from this session in order.
+        let database = self.app_state.database.clone();
+        let username = self.username.clone();
+        let insert_client_id = client_id.clone();
+
+        async move { Message::insert(&database, &username, &text, conversation_id, insert_client_id.as_deref()).await }
+            .into_actor(self)
+            .map(move |result, act, ctx| {
+                let response = match result{
+                    Ok(Some((message, is_new))) => {
+                        let response = ServerMessage::MessageAck {
+                            request_id,
+                            client_id,
+                            conversation_id,
+                            message_id: message.id,
+                            sent_at: message.sent_at,
+                        };
+
+                        // send message to chat server, unless it was already sent by a previous attempt.
+                        if is_new{
+                            act.app_state.websocket_server.do_send(server::ClientMessage {
+                                id: act.id,
+                                event: Event::Message { conversation_id, message },
+                                conversation: conversation_id,
+                            });
+                        }
+                        response
+                    }
+                    Ok(None) => ServerMessage::error(request_id, ErrorCode::Conflict, "Client id is already used by a message of another conversation."),
+                    Err(err) => {
+                        log::error!("Failed to persist websocket message: {err}");
+                        ServerMessage::error(request_id, ErrorCode::InternalError, "Internal server error.")
+                    }
+                };
+                Self::reply(ctx, response);
+            })
+            .wait(ctx);
+    }
+}
+
 impl Actor for WsChatSession {
     type Context = ws::WebsocketContext<Self>;
 
@@ -67,6 +215,9 @@ impl Actor for WsChatSession {
         // we'll start heartbeat process on session start.
         self.hb(ctx);
 
+        // let the client know which protocol version we speak.
+        Self::reply(ctx, ServerMessage::Event(Event::Hello { version: PROTOCOL_VERSION }));
+
         // register self in chat server. `AsyncContext::wait` register
         // future within context, but context waits until this future resolves
         // before processing any other events.
@@ -76,6 +227,8 @@ impl Actor for WsChatSession {
         self.app_state.websocket_server
             .send(server::Connect {
                 addr: addr.recipient(),
+                username: self.username.clone(),
+                token_id: self.token_id,
             })
             .into_actor(self)
             .then(|res, act, ctx| {
@@ -101,15 +254,41 @@ impl Handler<server::Message> for WsChatSession {
     type Result = ();
 
     fn handle(&mut self, msg: server::Message, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+        // Drop the frames of the conversations which are not subscribed. A `Subscribe` whose membership check raced
+        // with the removal of the user may reach `ChatServer` after the `conversation_removed` event: unsubscribe it
+        // again.
+        if let Some(conversation_id) = msg.conversation_id{
+            if !self.subscriptions.contains(&conversation_id){
+                self.app_state.websocket_server.do_send(server::Unsubscribe { id: self.id, conversation_ids: vec![conversation_id] });
+                return;
+            }
+        }
+
+        // Skip the messages already sent by the replay on subscription.
+        if let Some((conversation_id, message_id)) = msg.chat_message{
+            if self.replayed_until.get(&conversation_id).is_some_and(|&replayed_message_id| message_id <= replayed_message_id){
+                return;
+            }
+        }
+
+        // Stop accepting messages for a conversation the user was removed from.
+        if let Some(conversation_id) = msg.unsubscribed {
+            self.subscriptions.remove(&conversation_id);
+            self.replayed_until.remove(&conversation_id);
+        }
+
+        ctx.text(msg.frame);
+
+        if msg.close {
+            ctx.close(Some(ws::CloseCode::Policy.into()));
+            ctx.stop();
+        }
     }
 }
 
 /// WebSocket message handler
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsChatSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
-        let AppState { database: _, websocket_server } = self.app_state.get_ref();
-
         let msg = match msg {
             Ok(msg) => msg,
             Err(_) => {
@@ -128,64 +307,28 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsChatSession {
                 self.hb = Instant::now();
             }
             ws::Message::Text(text) => {
-                // let text = text.trim();
-
-                // we check for /sss type of messages
-                if text.starts_with('/') {
-                    let v: Vec<&str> = text.splitn(2, ' ').collect();
-                    match v[..]{
-                        ["/join", conversation_id] => {
-                            let conversation_id = conversation_id.parse::<i64>().unwrap();
-
-                            // Check if user joined to the given conversation.
-                            // let is_user_joined_fut = async {
-                            //      sqlx::query("SELECT 1 FROM group_members WHERE username = ? AND conversation_id = ?;")
-                            //         .bind(self.username)
-                            //         .bind(&conversation_id)
-                            //         .fetch_optional(database)
-                            //         .await.unwrap()
-                            //         .is_some()
-                            // };
-                            // let is_user_joined_fut = actix::fut::wrap_future(is_user_joined_fut)
-                            //     .map(|result, actor, ctx: &mut Self::Context| { 
-                            //         if result{
-                            //         }
-                            //         else{
-                            //             ctx.text("You are not joined to this conversation.");
-                            //             ctx.stop();
-                            //         }
-                            //     });
-                            // ctx.spawn(is_user_joined_fut);
-
-                            self.conversation_id = conversation_id;
-                            websocket_server.do_send(server::Join {
-                                id: self.id,
-                                conversation_id
-                            });
+                let frame = match serde_json::from_str::<ClientFrame>(&text){
+                    Ok(frame) => frame,
+                    Err(err) => {
+                        Self::reply(ctx, ServerMessage::error(None, ErrorCode::InvalidRequest, err.to_string()));
+                        return;
+                    }
+                };
 
-                            ctx.text(serde_json::to_string(&WebsocketResponse::JoinStatus { success: true }).unwrap());
-                        }
-                        _ => ctx.text(serde_json::to_string(&WebsocketResponse::InvalidRequest).unwrap()),
+                if frame.v != PROTOCOL_VERSION{
+                    Self::reply(ctx, ServerMessage::error(frame.request_id, ErrorCode::UnsupportedVersion,
+                        format!("Unsupported protocol version, use {PROTOCOL_VERSION}.")));
+                    return;
+                }
+
+                match frame.request{
+                    Request::Subscribe { conversation_ids, last_seen_message_ids } => {
+                        self.subscribe(frame.request_id, conversation_ids, last_seen_message_ids, ctx)
+                    }
+                    Request::Unsubscribe { conversation_ids } => self.unsubscribe(frame.request_id, conversation_ids, ctx),
+                    Request::Send { conversation_id, text, client_id } => {
+                        self.send_message(frame.request_id, conversation_id, text, client_id, ctx)
                     }
-                } else { // Message received.
-                    // let username = self.username.clone();
-                    // let text = text.clone().to_string();
-                    
-                    // let future = async move { 
-                    //     sqlx::query!("INSERT INTO messages (sender_username, text, sent_at) VALUES (?, ?, DATETIME('NOW'));", username, text)
-                    //         .execute(database);
-                    // };  
-                    // let future = actix::fut::wrap_future(future);
-                    // ctx.wait(future);
-                        // .await
-                        // .unwrap();
-                        
-                    // send message to chat server
-                    websocket_server.do_send(server::ClientMessage {
-                        id: self.id,
-                        msg: text.to_string(),
-                        conversation: self.conversation_id,
-                    })
                 }
             }
             ws::Message::Close(reason) => {